@@ -0,0 +1,225 @@
+//! # auxfile
+//! Drop-in replacement for the `bibtex` program: reads the citations and
+//! databases that LaTeX wrote to the .aux file and writes the .bbl file that
+//! `\bibliography{}` reads back, formatted by `compile`.
+//!
+//! `\citation{}`, `\bibdata{}` and `\bibstyle{}` are read, following the
+//! `\@input{}` of included chapters; `\citation{*}` cites every entry. With a
+//! sorting style (`plain`, `alpha`, ...) entries are sorted by author, year
+//! and title, otherwise they follow the order of citation.
+//!
+//! Like BibTeX, it exits with a failure status after an error, such as a
+//! missing .aux or .bib file, a parse error or an .aux file without
+//! `\citation` or `\bibdata` commands, so that build tools stop.
+//!
+//! Run it as `latex-thebib bibtex main`, or set `$bibtex = 'latex-thebib bibtex %O %B';`
+//! in latexmkrc.
+use crate::bibtex::{Block, Entry};
+use crate::compile::{self, CompileOptions};
+use crate::diagnostics::{self, DiagnosticsCli};
+use crate::import;
+use crate::names;
+use crate::unicode;
+use crate::utils;
+use clap::Args;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct BibtexCli {
+    /// .aux file, the extension may be omitted
+    aux: String,
+    #[command(flatten)]
+    options: CompileOptions,
+    #[command(flatten)]
+    pub diagnostics: DiagnosticsCli,
+}
+
+/// Styles of the standard distributions that sort the bibliography.
+const SORTED_STYLES: [&str; 10] = [
+    "plain", "abbrv", "alpha", "apalike", "acm", "siam", "amsplain", "amsalpha", "plainnat",
+    "abbrvnat",
+];
+
+#[derive(Default)]
+struct AuxData {
+    citations: Vec<String>,
+    databases: Vec<String>,
+    style: Option<String>,
+}
+
+pub fn run_bibtex(cli: &BibtexCli) {
+    let aux = if cli.aux.ends_with(".aux") {
+        PathBuf::from(&cli.aux)
+    } else {
+        PathBuf::from(format!("{}.aux", cli.aux))
+    };
+    let mut data = AuxData::default();
+    if let Err(e) = read_aux(&aux, &mut data) {
+        diagnostics::error(e);
+        return;
+    }
+    if data.databases.is_empty() {
+        diagnostics::error(format!("{}: no \\bibdata command", aux.display()));
+        return;
+    }
+    // as BibTeX does, rather than compiling the whole database
+    if data.citations.is_empty() {
+        diagnostics::error(format!("{}: no \\citation commands", aux.display()));
+        return;
+    }
+    let blocks = match read_databases(&aux, &data) {
+        Ok(blocks) => blocks,
+        Err(e) => {
+            diagnostics::error(e);
+            return;
+        }
+    };
+    let all = data.citations.iter().any(|c| c == "*");
+    let only = if all { vec![] } else { data.citations.clone() };
+    let Some(mut bib) = compile::prepare(&blocks, &only, &cli.options) else {
+        return;
+    };
+    // like BibTeX, only the first of entries sharing a key is used
+    let mut seen = HashSet::new();
+    bib.retain(|e| {
+        let first = seen.insert(e.key.to_lowercase());
+        if !first {
+            diagnostics::warning(format!(
+                "repeated entry \"{}\", only the first one is used",
+                e.key
+            ));
+        }
+        first
+    });
+    let sorted = data
+        .style
+        .as_deref()
+        .is_some_and(|s| SORTED_STYLES.contains(&s));
+    if sorted {
+        bib.sort_by_cached_key(|e| {
+            (
+                sort_name(e),
+                e.get("year").unwrap_or_default().to_owned(),
+                unicode::sort_key(e.get("title").unwrap_or_default()),
+            )
+        });
+    } else if !all {
+        // order of first citation, uncited crossref parents last
+        let mut position = HashMap::new();
+        for (i, c) in data.citations.iter().enumerate() {
+            position.entry(c.to_lowercase()).or_insert(i);
+        }
+        bib.sort_by_key(|e| {
+            position
+                .get(&e.key.to_lowercase())
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
+    }
+    let bbl = aux.with_extension("bbl");
+    diagnostics::info(format!(
+        "wrote {} with {} entries",
+        bbl.display(),
+        bib.len()
+    ));
+    utils::write_file(
+        bbl.to_str().unwrap().to_owned(),
+        &format!("{}\n", compile::format_thebibliography(bib, &cli.options)),
+    );
+}
+
+/// Reads an .aux file and the ones it includes with `\@input`.
+fn read_aux(fname: &Path, data: &mut AuxData) -> Result<(), String> {
+    let Ok(contents) = std::fs::read_to_string(fname) else {
+        return Err(format!("Unable to read file \"{}\"", fname.display()));
+    };
+    let re = Regex::new(r"\\(citation|bibdata|bibstyle|@input)\{([^}]*)\}").unwrap();
+    for cap in re.captures_iter(&contents) {
+        let list = cap[2]
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty());
+        match &cap[1] {
+            "citation" => {
+                for c in list {
+                    if !data.citations.iter().any(|d| d == c) {
+                        data.citations.push(c.to_owned());
+                    }
+                }
+            }
+            "bibdata" => data.databases.extend(list.map(|s| s.to_owned())),
+            "bibstyle" => data.style = Some(cap[2].trim().to_owned()),
+            _ => {
+                let dir = fname.parent().unwrap_or(Path::new(""));
+                let child = dir.join(cap[2].trim());
+                // chapters that were not compiled yet have no .aux
+                if child.exists() {
+                    read_aux(&child, data)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the `\bibdata` databases, relative to the directory of the .aux file.
+fn read_databases(aux: &Path, data: &AuxData) -> Result<Vec<Block>, String> {
+    let dir = aux.parent().unwrap_or(Path::new(""));
+    let mut blocks = vec![];
+    for db in data.databases.iter() {
+        let fname = if Path::new(db).extension().is_some() {
+            dir.join(db)
+        } else {
+            dir.join(format!("{}.bib", db))
+        };
+        // % is not a comment in .bib files, e.g. in percent-encoded URLs
+        blocks.append(&mut import::read_blocks(fname.to_str().unwrap(), false)?);
+    }
+    Ok(blocks)
+}
+
+/// Sort key of the first author, or editor, of an entry.
+fn sort_name(e: &Entry) -> String {
+    let list = e
+        .get("author")
+        .or_else(|| e.get("editor"))
+        .unwrap_or_default();
+    match names::parse_names(list).first() {
+        Some(n) => unicode::sort_key(&format!("{} {} {}", n.last, n.first, n.jr)),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_percent_encoded_urls() {
+        let dir = std::env::temp_dir().join(format!("latex-thebib-aux-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let aux = dir.join("main.aux");
+        std::fs::write(&aux, "\\citation{key}\n\\bibdata{refs}\n").unwrap();
+        std::fs::write(
+            dir.join("refs.bib"),
+            "@misc{key,\n  title = {T},\n  url = {http://x.org/a%20b}\n}\n",
+        )
+        .unwrap();
+        let mut data = AuxData::default();
+        read_aux(&aux, &mut data).unwrap();
+        let blocks = read_databases(&aux, &data).unwrap();
+        let entries: Vec<&Entry> = blocks
+            .iter()
+            .filter_map(|b| match b {
+                Block::Entry(e) => Some(e),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get("url"), Some("http://x.org/a%20b"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
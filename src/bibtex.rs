@@ -0,0 +1,539 @@
+//! # bibtex
+//! BibTeX database parser and canonical writer, shared by the commands that
+//! read .bib files.
+//!
+//! Field values are kept as written (`{...}`, `"..."`, numbers, `@string`
+//! macros and `#` concatenations), so that a database can be written back
+//! without losing information.
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub kind: String,
+    pub key: String,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Entry(Entry),
+    String(Field),
+    Preamble(String),
+    Comment(String),
+}
+
+/// Fields printed first, in this order, by the canonical writer; the others follow alphabetically.
+const FIELD_ORDER: [&str; 36] = [
+    "author",
+    "editor",
+    "title",
+    "subtitle",
+    "booktitle",
+    "journal",
+    "journaltitle",
+    "series",
+    "volume",
+    "number",
+    "pages",
+    "chapter",
+    "edition",
+    "publisher",
+    "organization",
+    "institution",
+    "school",
+    "address",
+    "location",
+    "howpublished",
+    "type",
+    "month",
+    "year",
+    "date",
+    "crossref",
+    "xdata",
+    "note",
+    "isbn",
+    "issn",
+    "doi",
+    "eprint",
+    "archiveprefix",
+    "primaryclass",
+    "url",
+    "urldate",
+    "abstract",
+];
+
+const MONTHS: [(&str, &str); 12] = [
+    ("jan", "January"),
+    ("feb", "February"),
+    ("mar", "March"),
+    ("apr", "April"),
+    ("may", "May"),
+    ("jun", "June"),
+    ("jul", "July"),
+    ("aug", "August"),
+    ("sep", "September"),
+    ("oct", "October"),
+    ("nov", "November"),
+    ("dec", "December"),
+];
+
+/// Required fields of each entry type, as in the standard BibTeX styles; `a|b` is either field.
+/// Types not listed only require a title.
+const REQUIRED: [(&str, &[&str]); 16] = [
+    ("article", &["author", "title", "journal", "year|date"]),
+    (
+        "book",
+        &["author|editor", "title", "publisher", "year|date"],
+    ),
+    ("booklet", &["title"]),
+    (
+        "inbook",
+        &[
+            "author|editor",
+            "title",
+            "chapter|pages",
+            "publisher",
+            "year|date",
+        ],
+    ),
+    (
+        "incollection",
+        &["author", "title", "booktitle", "publisher", "year|date"],
+    ),
+    (
+        "inproceedings",
+        &["author", "title", "booktitle", "year|date"],
+    ),
+    ("conference", &["author", "title", "booktitle", "year|date"]),
+    ("manual", &["title"]),
+    ("mastersthesis", &["author", "title", "school", "year|date"]),
+    ("phdthesis", &["author", "title", "school", "year|date"]),
+    ("misc", &[]),
+    ("online", &["title", "url"]),
+    ("proceedings", &["title", "year|date"]),
+    (
+        "techreport",
+        &["author", "title", "institution", "year|date"],
+    ),
+    ("unpublished", &["author", "title", "note"]),
+    ("patent", &["author", "title", "number", "year|date"]),
+];
+
+impl Entry {
+    /// Value of a field with delimiters removed, field names are case-insensitive.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.field(name).map(|f| unquote(&f.value))
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Names of the fields that appear more than once.
+    pub fn duplicate_fields(&self) -> Vec<String> {
+        let mut dups: Vec<String> = vec![];
+        for (i, f) in self.fields.iter().enumerate() {
+            let name = f.name.to_lowercase();
+            if self.fields[..i]
+                .iter()
+                .any(|g| g.name.eq_ignore_ascii_case(&name))
+                && !dups.contains(&name)
+            {
+                dups.push(name);
+            }
+        }
+        dups
+    }
+
+    /// Required fields of the entry type that are missing, see `REQUIRED`.
+    pub fn missing_fields(&self) -> Vec<&'static str> {
+        let required: &[&str] = REQUIRED
+            .iter()
+            .find(|(kind, _)| self.kind.eq_ignore_ascii_case(kind))
+            .map(|(_, fields)| *fields)
+            .unwrap_or(&["title"]);
+        required
+            .iter()
+            .filter(|r| r.split('|').all(|name| self.get(name).is_none()))
+            .copied()
+            .collect()
+    }
+}
+
+/// Canonical form: lowercase entry type and field names, two-space indentation,
+/// fields in `FIELD_ORDER`, brace-delimited values, first occurrence of duplicated fields only.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields: Vec<&Field> = vec![];
+        for field in self.fields.iter() {
+            if !fields
+                .iter()
+                .any(|g| g.name.eq_ignore_ascii_case(&field.name))
+            {
+                fields.push(field);
+            }
+        }
+        let rank = |name: &str| {
+            let name = name.to_lowercase();
+            let pos = FIELD_ORDER.iter().position(|n| *n == name);
+            (pos.unwrap_or(FIELD_ORDER.len()), name)
+        };
+        fields.sort_by_cached_key(|field| rank(&field.name));
+        writeln!(f, "@{}{{{},", self.kind.to_lowercase(), self.key)?;
+        for field in fields {
+            writeln!(
+                f,
+                "  {} = {},",
+                field.name.to_lowercase(),
+                canonical_value(&field.value)
+            )?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Block::Entry(e) => write!(f, "{}", e),
+            Block::String(s) => write!(
+                f,
+                "@string{{{} = {}}}",
+                s.name.to_lowercase(),
+                canonical_value(&s.value)
+            ),
+            Block::Preamble(p) => write!(f, "@preamble{{{}}}", collapse_whitespace(p)),
+            Block::Comment(c) => write!(f, "@comment{{{}}}", c),
+        }
+    }
+}
+
+/// Parses a whole database, text outside of `@` blocks is ignored as BibTeX does.
+pub fn parse(data: &str) -> Result<Vec<Block>, String> {
+    let mut blocks = vec![];
+    let mut pos = 0;
+    while let Some(at) = data[pos..].find('@') {
+        let mut p = Parser {
+            data,
+            pos: pos + at + 1,
+        };
+        p.ident();
+        p.skip_ws();
+        if !matches!(p.peek(), Some('{') | Some('(')) {
+            // a stray "@" in the free text between entries
+            pos += at + 1;
+            continue;
+        }
+        p.pos = pos + at + 1;
+        blocks.push(p.block()?);
+        pos = p.pos;
+    }
+    Ok(blocks)
+}
+
+/// Values of the `@string` macros of a database, including the standard month names.
+pub fn string_table(blocks: &[Block]) -> HashMap<String, String> {
+    let mut strings = MONTHS
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<HashMap<String, String>>();
+    for b in blocks {
+        if let Block::String(s) = b {
+            let v = expand(&s.value, &strings);
+            strings.entry(s.name.to_lowercase()).or_insert(v);
+        }
+    }
+    strings
+}
+
+/// Entries of a database, with `@string` macros and concatenations expanded.
+pub fn entries(blocks: &[Block]) -> Vec<Entry> {
+    let strings = string_table(blocks);
+    let mut entries = vec![];
+    for b in blocks {
+        if let Block::Entry(e) = b {
+            let mut e = e.clone();
+            for f in e.fields.iter_mut() {
+                let parts = split_parts(&f.value);
+                if parts.len() > 1 || parts.iter().any(|p| is_macro(p)) {
+                    f.value = format!("{{{}}}", expand(&f.value, &strings));
+                }
+            }
+            entries.push(e);
+        }
+    }
+    entries
+}
+
+/// Month number of a `month` value: `3`, `mar`, `March` or `march`.
+pub fn month_number(value: &str) -> Option<u32> {
+    let v = value.trim().to_lowercase();
+    if let Ok(n) = v.parse::<u32>() {
+        return (1..=12).contains(&n).then_some(n);
+    }
+    MONTHS
+        .iter()
+        .position(|(k, name)| *k == v || (v.len() >= 3 && name.to_lowercase().starts_with(&v)))
+        .map(|i| i as u32 + 1)
+}
+
+/// Macro of a month number, `1` is `jan`.
+pub fn month_macro(n: u32) -> &'static str {
+    MONTHS[(n as usize - 1) % 12].0
+}
+
+/// Text of a value, with delimiters removed and macros replaced by their definition.
+pub fn expand(value: &str, strings: &HashMap<String, String>) -> String {
+    split_parts(value)
+        .iter()
+        .map(|p| {
+            if is_macro(p) {
+                strings
+                    .get(&p.to_lowercase())
+                    .cloned()
+                    .unwrap_or(p.to_string())
+            } else {
+                delimited(p).to_owned()
+            }
+        })
+        .collect()
+}
+
+/// Removes the delimiters of a single value, `{...}` or `"..."`.
+pub fn unquote(value: &str) -> &str {
+    let v = value.trim();
+    let b = v.as_bytes();
+    if b.len() >= 2
+        && ((b[0] == b'{' && b[b.len() - 1] == b'}') || (b[0] == b'"' && b[b.len() - 1] == b'"'))
+        && split_parts(v).len() == 1
+    {
+        return v[1..v.len() - 1].trim();
+    }
+    v
+}
+
+/// Text of a single part of a value, `{...}` or `"..."`, spaces included since
+/// `"Quick " # "Dirty"` concatenates to `Quick Dirty`.
+fn delimited(part: &str) -> &str {
+    let b = part.as_bytes();
+    if b.len() >= 2 && matches!((b[0], b[b.len() - 1]), (b'{', b'}') | (b'"', b'"')) {
+        return &part[1..part.len() - 1];
+    }
+    part
+}
+
+fn is_macro(part: &str) -> bool {
+    part.chars().next().is_some_and(|c| c.is_alphabetic())
+}
+
+/// Splits a value on the top-level `#` concatenation operators.
+fn split_parts(value: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut level = 0;
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '{' => level += 1,
+            '}' => level -= 1,
+            '"' if level == 0 => quoted = !quoted,
+            '#' if level == 0 && !quoted => {
+                parts.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+    parts
+}
+
+fn canonical_value(value: &str) -> String {
+    let parts = split_parts(value);
+    let single = parts.len() == 1;
+    parts
+        .iter()
+        .map(|p| {
+            if is_macro(p) {
+                p.to_lowercase()
+            } else if single {
+                format!("{{{}}}", collapse_whitespace(unquote(p)))
+            } else {
+                format!("{{{}}}", squeeze_whitespace(delimited(p)))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" # ")
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Collapses runs of whitespace into a single space, keeping the leading and trailing ones.
+fn squeeze_whitespace(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        if !c.is_whitespace() {
+            out.push(c);
+        } else if !out.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+    out
+}
+
+struct Parser<'a> {
+    data: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> String {
+        let line = self.data[..self.pos].matches('\n').count() + 1;
+        format!("Parse error at line {}: {}", line, msg)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.data[self.pos..].chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn ident(&mut self) -> &str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "{}()=,#\"".contains(c) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        &self.data[start..self.pos]
+    }
+
+    /// Consumes a balanced `{...}` group (or a quoted string) and returns it with its delimiters.
+    fn delimited(&mut self, open: char, close: char) -> Result<&str, String> {
+        let start = self.pos;
+        self.pos += open.len_utf8();
+        let mut level = 0;
+        while let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            if level == 0 && c == close {
+                return Ok(&self.data[start..self.pos]);
+            }
+            match c {
+                '{' => level += 1,
+                '}' => level -= 1,
+                _ => {}
+            }
+        }
+        self.pos = start;
+        Err(self.error(&format!("missing closing \"{}\"", close)))
+    }
+
+    fn block(&mut self) -> Result<Block, String> {
+        let kind = self.ident().to_owned();
+        self.skip_ws();
+        let close = if self.peek() == Some('(') { ')' } else { '}' };
+        match kind.to_lowercase().as_str() {
+            "comment" => {
+                let body = self.delimited(self.peek().unwrap(), close)?;
+                Ok(Block::Comment(body[1..body.len() - 1].to_owned()))
+            }
+            "preamble" => {
+                let body = self.delimited(self.peek().unwrap(), close)?;
+                Ok(Block::Preamble(body[1..body.len() - 1].trim().to_owned()))
+            }
+            "string" => {
+                self.pos += 1;
+                let field = self.field()?;
+                self.skip_ws();
+                if self.peek() != Some(close) {
+                    return Err(self.error(&format!("expected \"{}\" after @string", close)));
+                }
+                self.pos += 1;
+                Ok(Block::String(field))
+            }
+            _ => {
+                self.pos += 1;
+                self.skip_ws();
+                let key = self.ident().to_owned();
+                let mut entry = Entry {
+                    kind,
+                    key,
+                    fields: vec![],
+                };
+                loop {
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(c) if c == close => {
+                            self.pos += 1;
+                            return Ok(Block::Entry(entry));
+                        }
+                        Some(_) => entry.fields.push(self.field()?),
+                        None => {
+                            return Err(self.error(&format!(
+                                "expected final \"{}\" for entry \"{}\"",
+                                close, entry.key
+                            )))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn field(&mut self) -> Result<Field, String> {
+        self.skip_ws();
+        let name = self.ident().to_owned();
+        self.skip_ws();
+        if name.is_empty() || self.peek() != Some('=') {
+            return Err(self.error(&format!("expected \"=\" after field name \"{}\"", name)));
+        }
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some('{') => {
+                    self.delimited('{', '}')?;
+                }
+                Some('"') => {
+                    self.delimited('"', '"')?;
+                }
+                _ => {
+                    if self.ident().is_empty() {
+                        return Err(self.error(&format!("missing value for field \"{}\"", name)));
+                    }
+                }
+            }
+            self.skip_ws();
+            if self.peek() == Some('#') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(Field {
+            name,
+            value: self.data[start..self.pos].trim().to_owned(),
+        })
+    }
+}
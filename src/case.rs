@@ -0,0 +1,294 @@
+//! # case
+//! Case conversion of titles, following the rules of BibTeX styles: text in
+//! braces (`{DNA}`) and math (`$\alpha$`) is never touched, command names are
+//! kept, and special characters written as `{\"O}` change case like letters.
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum TitleCase {
+    /// keep titles as written
+    AsIs,
+    /// only the first word (and proper nouns in braces) capitalized
+    Sentence,
+    /// every major word capitalized
+    Title,
+}
+
+const MINOR_WORDS: [&str; 22] = [
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into", "nor", "of", "on",
+    "or", "over", "per", "the", "to", "upon", "vs", "with",
+];
+
+/// Special characters whose command name carries the case.
+const CASED_COMMANDS: [(&str, &str); 5] = [
+    ("AA", "aa"),
+    ("AE", "ae"),
+    ("OE", "oe"),
+    ("O", "o"),
+    ("L", "l"),
+];
+
+enum Piece<'a> {
+    /// Unprotected text, whose letters may change case.
+    Text(&'a str),
+    /// A `\command` name, kept as is.
+    Command(&'a str),
+    /// A top-level `{\"O}` group, changing case like a letter.
+    Special(&'a str),
+    /// Braced text or math, kept as is.
+    Protected(&'a str),
+}
+
+fn pieces<'a>(s: &'a str) -> Vec<Piece<'a>> {
+    let b = s.as_bytes();
+    let mut out = vec![];
+    let mut start = 0;
+    let mut i = 0;
+    let flush = |out: &mut Vec<Piece<'a>>, start: usize, end: usize| {
+        if end > start {
+            out.push(Piece::Text(&s[start..end]));
+        }
+    };
+    while i < b.len() {
+        match b[i] {
+            b'\\' => {
+                flush(&mut out, start, i);
+                let mut j = i + 1;
+                while j < b.len() && b[j].is_ascii_alphabetic() {
+                    j += 1;
+                }
+                if j == i + 1 && j < b.len() {
+                    j += s[j..].chars().next().unwrap().len_utf8();
+                }
+                out.push(Piece::Command(&s[i..j]));
+                i = j;
+                start = i;
+            }
+            b'{' => {
+                flush(&mut out, start, i);
+                let mut level = 0;
+                let mut j = i;
+                while j < b.len() {
+                    match b[j] {
+                        b'{' => level += 1,
+                        b'}' => level -= 1,
+                        _ => {}
+                    }
+                    j += 1;
+                    if level == 0 {
+                        break;
+                    }
+                }
+                let group = &s[i..j];
+                if group.starts_with("{\\") {
+                    out.push(Piece::Special(group));
+                } else {
+                    out.push(Piece::Protected(group));
+                }
+                i = j;
+                start = i;
+            }
+            b'$' => {
+                flush(&mut out, start, i);
+                let end = s[i + 1..].find('$').map(|e| i + e + 2).unwrap_or(b.len());
+                out.push(Piece::Protected(&s[i..end]));
+                i = end;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    flush(&mut out, start, b.len());
+    out
+}
+
+/// Changes the case of the letters of a special character group, `{\"O}` becomes `{\"o}`.
+fn special_case(group: &str, upper: bool) -> String {
+    let inner = &group[1..group.len() - 1];
+    let mut out = String::from("{");
+    for p in pieces(inner) {
+        match p {
+            Piece::Command(c) => {
+                let name = &c[1..];
+                let cased = CASED_COMMANDS.iter().find_map(|(u, l)| {
+                    if name == *u || name == *l {
+                        Some(if upper { *u } else { *l })
+                    } else {
+                        None
+                    }
+                });
+                match cased {
+                    Some(n) => {
+                        out.push('\\');
+                        out.push_str(n);
+                    }
+                    None => out.push_str(c),
+                }
+            }
+            Piece::Text(t) if upper => out.push_str(&t.to_uppercase()),
+            Piece::Text(t) => out.push_str(&t.to_lowercase()),
+            Piece::Special(t) | Piece::Protected(t) => out.push_str(t),
+        }
+    }
+    out.push('}');
+    out
+}
+
+/// Converts a title; `{Protected}` text, math and commands are kept.
+pub fn convert(title: &str, case: TitleCase) -> String {
+    if case == TitleCase::AsIs {
+        return title.to_owned();
+    }
+    let mut out = String::new();
+    // start of a word, and whether it is the first word of the title or of a subtitle
+    let mut word_start = true;
+    let mut first_word = true;
+    let mut after_colon = false;
+    let words = word_list(title);
+    let mut word_index = 0;
+    for p in pieces(title) {
+        match p {
+            Piece::Text(t) => {
+                for c in t.chars() {
+                    if c.is_alphabetic() {
+                        let upper = if word_start {
+                            let (word, mixed) = words
+                                .get(word_index)
+                                .map(|(w, m)| (w.as_str(), *m))
+                                .unwrap_or(("", false));
+                            word_index += 1;
+                            match case {
+                                TitleCase::Sentence => first_word,
+                                // iPhone, eBay
+                                _ if mixed => c.is_uppercase(),
+                                _ => {
+                                    first_word
+                                        || after_colon
+                                        || word_index == words.len()
+                                        || !MINOR_WORDS.contains(&word)
+                                }
+                            }
+                        } else {
+                            case == TitleCase::Title && c.is_uppercase()
+                        };
+                        if upper {
+                            out.extend(c.to_uppercase());
+                        } else {
+                            out.extend(c.to_lowercase());
+                        }
+                        word_start = false;
+                        first_word = false;
+                        after_colon = false;
+                    } else {
+                        out.push(c);
+                        if c.is_whitespace() || c == '-' {
+                            word_start = true;
+                        }
+                        if c == ':' || c == '?' || c == '!' {
+                            after_colon = true;
+                            first_word = case == TitleCase::Sentence && c == ':';
+                        }
+                    }
+                }
+            }
+            Piece::Special(g) => {
+                let upper = word_start && (first_word || case == TitleCase::Title);
+                if word_start {
+                    word_index += 1;
+                }
+                out.push_str(&special_case(g, upper));
+                word_start = false;
+                first_word = false;
+                after_colon = false;
+            }
+            Piece::Command(c) => out.push_str(c),
+            Piece::Protected(g) => {
+                if word_start {
+                    word_index += 1;
+                }
+                out.push_str(g);
+                word_start = false;
+                first_word = false;
+                after_colon = false;
+            }
+        }
+    }
+    out
+}
+
+/// Lowercase words of a title, for the minor word lookup, and whether they have internal capitals.
+fn word_list(title: &str) -> Vec<(String, bool)> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut mixed = false;
+    let mut in_word = false;
+    for p in pieces(title) {
+        match p {
+            Piece::Text(t) => {
+                for c in t.chars() {
+                    if c.is_alphabetic() {
+                        mixed |= in_word && c.is_uppercase();
+                        current.extend(c.to_lowercase());
+                        in_word = true;
+                    } else if c.is_whitespace() || c == '-' {
+                        if in_word {
+                            words.push((std::mem::take(&mut current), mixed));
+                        }
+                        mixed = false;
+                        in_word = false;
+                    }
+                }
+            }
+            Piece::Command(_) => {}
+            Piece::Special(_) | Piece::Protected(_) => {
+                current.push('_');
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push((current, mixed));
+    }
+    words
+}
+
+/// Unprotected words that BibTeX styles would lowercase although they look like acronyms or
+/// proper nouns: words with internal capitals, and capitalized words inside a sentence-case title.
+pub fn unprotected_capitals(title: &str) -> Vec<String> {
+    // words, and whether they start the title or a subtitle
+    let mut words: Vec<(&str, bool)> = vec![];
+    let mut starts = true;
+    for p in pieces(title) {
+        match p {
+            Piece::Text(t) => {
+                for w in t.split(|c: char| c.is_whitespace() || c == '/') {
+                    let word = w.trim_matches(|c: char| !c.is_alphanumeric());
+                    if !word.is_empty() {
+                        words.push((word, starts));
+                        starts = false;
+                    }
+                    if w.ends_with(':') {
+                        starts = true;
+                    }
+                }
+            }
+            Piece::Command(_) => {}
+            Piece::Special(_) | Piece::Protected(_) => starts = false,
+        }
+    }
+    let long = words.iter().filter(|(w, _)| w.chars().count() > 3);
+    let lowercase = long
+        .clone()
+        .filter(|(w, _)| w.starts_with(char::is_lowercase))
+        .count();
+    let sentence_case = 2 * lowercase > long.count();
+    let mut found: Vec<String> = vec![];
+    for (w, starts) in words {
+        let internal = w.chars().skip(1).any(char::is_uppercase);
+        let proper = sentence_case && !starts && w.starts_with(char::is_uppercase);
+        if (internal || proper) && !found.iter().any(|f| f == w) {
+            found.push(w.to_owned());
+        }
+    }
+    found
+}
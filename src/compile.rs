@@ -5,19 +5,51 @@
 //!
 //! Run it as `bibcompiler -f master.bib` for basic functionality.
 
+use crate::bibtex::{self, Block, Entry};
+use crate::case::{self, TitleCase};
+use crate::crossref;
+use crate::csl;
+use crate::diagnostics::{self, DiagnosticsCli};
+use crate::import;
+use crate::journals::{JournalList, JournalMode};
+use crate::names;
+use crate::pages::{PageStyle, Pages};
+use crate::thebibliography;
+use crate::unicode;
 use crate::utils;
-use clap::Args;
-use std::{collections::HashMap, usize};
+use crate::volume::{self, Pattern};
+use crate::watch;
+use crate::web;
+use clap::{Args, FromArgMatches, ValueEnum};
+use std::collections::{HashMap, HashSet};
 pub const DEF_OUTPUT: &str = "print to stdout.";
 
 #[derive(Args)]
 pub struct CompileCli {
-    #[arg(short, long)]
-    /// Master BibTeX file
-    file: String,
+    #[arg(short, long, required = true, num_args = 1..)]
+    /// Master BibTeX file, or several BibTeX, RIS and EndNote XML files
+    file: Vec<String>,
     #[arg(short, long, default_value = DEF_OUTPUT)]
     /// Output TeX file name, it will contain a `thebibliography` environment.
     output: String,
+    #[arg(long, value_enum, default_value_t = Format::Tex)]
+    /// output format
+    format: Format,
+    #[arg(long, num_args = 1..)]
+    /// compile only these entries, and the crossref parents they refer to often enough
+    only: Vec<String>,
+    #[arg(short, long, default_value_t = false)]
+    /// compile again whenever an input file changes, until interrupted
+    watch: bool,
+    #[command(flatten)]
+    pub options: CompileOptions,
+    #[command(flatten)]
+    pub diagnostics: DiagnosticsCli,
+}
+
+/// How entries are prepared and formatted, shared with the commands that compile a bibliography.
+#[derive(Args)]
+pub struct CompileOptions {
     #[arg(short, long, default_value_t = false)]
     /// add "Publisher" segment to each `bibitem`.
     publisher: bool,
@@ -30,108 +62,439 @@ pub struct CompileCli {
     #[arg(short, long, default_value = "")]
     /// add a prefix to each cite label
     cite_prefix: String,
+    #[arg(long, default_value_t = false, conflicts_with = "ascii_tex")]
+    /// write accented letters and special characters as Unicode instead of TeX macros
+    unicode: bool,
+    #[arg(long, default_value_t = false)]
+    /// write accented letters and special characters as ASCII TeX macros
+    ascii_tex: bool,
+    #[arg(long, value_enum, default_value_t = TitleCase::AsIs)]
+    /// convert titles to sentence case or title case, `{Braced}` text and math are kept
+    title_case: TitleCase,
+    #[arg(long, value_enum, default_value_t = JournalMode::Asis, requires = "journal_list")]
+    /// abbreviate or expand journal names, using the journal lists
+    journal: JournalMode,
+    #[arg(long, num_args = 1..)]
+    /// journal abbreviation lists, in the JabRef CSV format `Full Name;Abbreviation`
+    journal_list: Vec<String>,
+    #[arg(long, value_enum, default_value_t = PageStyle::Full)]
+    /// how to write page ranges, article numbers are always written alone
+    pages: PageStyle,
+    #[arg(long, default_value = volume::DEF_VOLUME_FORMAT, value_parser = Pattern::parse)]
+    /// layout of volume (%v), number (%n) and pages (%p); `[...]` is optional, `|` separates alternatives
+    volume_format: Pattern,
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "href")]
+    /// append the DOI, as a hyperref link (default) or with the `\doi` command of the doi package
+    doi: Option<DoiStyle>,
+    #[arg(long, default_value_t = false)]
+    /// append the URL with `\url`
+    url: bool,
+    #[arg(long, default_value_t = false)]
+    /// append the arXiv identifier and class, `arXiv:2101.00001 [hep-th]`
+    eprint: bool,
+    #[arg(long, default_value_t = false)]
+    /// append the note field
+    note: bool,
+    #[arg(long, default_value_t = false)]
+    /// append the ISBN
+    isbn: bool,
+    #[arg(long)]
+    /// write the entries missing required fields to this JSON file
+    report: Option<String>,
+    #[arg(long, default_value_t = 2)]
+    /// include a crossref parent not given to `--only` when this many entries refer to it
+    min_crossrefs: usize,
 }
 
-type StrMap<'a> = HashMap<&'a str, &'a str>;
-#[derive(Debug)]
-struct Entry<'a> {
-    name: &'a str,
-    params: StrMap<'a>,
+impl Default for CompileOptions {
+    /// The options of a plain `compile`, none given on the command line.
+    fn default() -> Self {
+        let cmd = CompileOptions::augment_args(clap::Command::new("compile"));
+        CompileOptions::from_arg_matches(&cmd.get_matches_from(["compile"])).unwrap()
+    }
 }
-pub fn run_compile(cli: &CompileCli) {
-    if let Some(data) = utils::read_tex_stripped(&cli.file) {
-        let mut bib = parse_bibliography(&data);
-        if cli.sort {
-            bib.sort_by(|a, b| {
-                let ya = &a.params.get("year").unwrap().parse::<usize>().unwrap();
-                let yb = &b.params.get("year").unwrap().parse::<usize>().unwrap();
-                yb.partial_cmp(ya).unwrap()
-            });
-        }
-        let size = utils::thebibliography_size(bib.len());
-        let mut formatted = if cli.aslist {
-            format!("\\begin{{enumerate}}\n")
-        } else {
-            format!("\\begin{{thebibliography}}{{{size}}}\n\n")
-        };
-        for (n, b) in bib.into_iter().enumerate() {
-            let citename = format!("{}{}", cli.cite_prefix, b.name);
-            let a = &b.params.get("author").unwrap();
-            let authors = format_all_author(a);
-            let t = format!("\\textit{{{}}},", &b.params.get("title").unwrap());
-            let y = format!("({})", &b.params.get("year").unwrap());
 
-            let p = if let Some(tmp) = &b.params.get("publisher") {
-                format!("- {}", tmp)
-            } else {
-                "".to_owned()
-            };
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum DoiStyle {
+    /// `\href{https://doi.org/...}{doi:...}`, needs hyperref
+    Href,
+    /// `\doi{...}`, needs the doi package
+    Command,
+}
 
-            let j = &b.params.get("journal").unwrap_or(&"");
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    /// `thebibliography` environment
+    Tex,
+    /// CSL-JSON, as read by Pandoc and other citeproc processors
+    CslJson,
+    /// HTML ordered list, items anchored by their cite label
+    Html,
+    /// Markdown numbered list, items anchored by their cite label
+    Markdown,
+}
 
-            let vol_fmt = format_volume(&b);
+pub fn run_compile(cli: &CompileCli) {
+    let mut cache = HashMap::new();
+    if cli.watch {
+        watch::watch(|| cli.file.clone(), || compile(cli, &mut cache));
+    }
+    compile(cli, &mut cache);
+}
 
-            let bibkey = if cli.aslist {
-                format!("\\item[({})] ", n + 1)
-            } else {
-                format!("\\bibitem{{{}}}", citename)
-            };
+/// Parsed input files with their stamp, so that watch mode only reparses the changed ones.
+pub type Cache = HashMap<String, (watch::Stamp, Vec<Block>)>;
+
+pub fn read_cached(fname: &str, cache: &mut Cache) -> Result<Vec<Block>, String> {
+    let stamp = watch::stamp(fname);
+    if let Some((s, blocks)) = cache.get(fname) {
+        if stamp.is_some() && *s == stamp {
+            return Ok(blocks.clone());
+        }
+    }
+    let blocks = import::read_blocks(fname, false)?;
+    cache.insert(fname.to_owned(), (stamp, blocks.clone()));
+    Ok(blocks)
+}
 
-            let mut elements: Vec<&str> = vec![];
-            elements.push(&bibkey);
-            elements.push(&authors);
-            elements.push(&t);
-            elements.push(&j);
-            elements.push(&vol_fmt);
-            if cli.publisher {
-                elements.push(&p);
+fn compile(cli: &CompileCli, cache: &mut Cache) {
+    let mut blocks = vec![];
+    for fname in cli.file.iter() {
+        match read_cached(fname, cache) {
+            Ok(mut b) => {
+                diagnostics::info(format!("read {} blocks from {}", b.len(), fname));
+                blocks.append(&mut b);
+            }
+            Err(e) => {
+                diagnostics::error(e);
+                return;
             }
-            elements.push(&y);
-            formatted.push_str(&format!(
-                "{}\n\n",
-                utils::clean_bib_text(&elements.join(" "))
-            ));
         }
-        if cli.aslist {
-            formatted.push_str("\\end{enumerate}");
-        } else {
-            formatted.push_str("\\end{thebibliography}");
+    }
+    let Some(bib) = prepare(&blocks, &cli.only, &cli.options) else {
+        return;
+    };
+    let n = bib.len();
+    let opts = &cli.options;
+    let formatted = match cli.format {
+        Format::Tex => format_thebibliography(bib, opts),
+        Format::CslJson => csl::write_csl(&bib, &opts.cite_prefix),
+        Format::Html => web::write_html(&web_items(&bib, opts)),
+        Format::Markdown => web::write_markdown(&web_items(&bib, opts)),
+    };
+    diagnostics::info(format!("compiled {} entries", n));
+    if cli.output != DEF_OUTPUT {
+        diagnostics::info(format!("wrote {}", cli.output));
+        utils::write_file(cli.output.to_owned(), &formatted);
+    } else {
+        println!("{}", formatted);
+    }
+}
+
+/// Entries of the blocks ready to be formatted: inheritance resolved, restricted to `only`
+/// (all when empty), fields converted and sorted as the options ask. `None` after an error.
+pub fn prepare(blocks: &[Block], only: &[String], opts: &CompileOptions) -> Option<Vec<Entry>> {
+    let mut bib = crossref::resolve(&bibtex::entries(blocks));
+    if !only.is_empty() {
+        bib = select(bib, only, opts.min_crossrefs);
+    }
+    if opts.title_case != TitleCase::AsIs {
+        for f in bib.iter_mut().flat_map(|b| b.fields.iter_mut()) {
+            if f.name.eq_ignore_ascii_case("title") {
+                f.value = format!(
+                    "{{{}}}",
+                    case::convert(bibtex::unquote(&f.value), opts.title_case)
+                );
+            }
+        }
+    }
+    if opts.journal != JournalMode::Asis {
+        let list = match JournalList::read(&opts.journal_list) {
+            Ok(list) => list,
+            Err(e) => {
+                diagnostics::error(e);
+                return None;
+            }
+        };
+        let mut unknown: Vec<String> = vec![];
+        for f in bib.iter_mut().flat_map(|b| b.fields.iter_mut()) {
+            if !f.name.eq_ignore_ascii_case("journal") {
+                continue;
+            }
+            let name = bibtex::unquote(&f.value).to_owned();
+            match list.convert(&name, opts.journal) {
+                Some(j) => f.value = import::field_value("journal", j),
+                None if !unknown.contains(&name) => unknown.push(name),
+                None => {}
+            }
         }
-        if cli.output != DEF_OUTPUT {
-            utils::write_file(cli.output.to_owned(), &formatted);
+        for j in unknown {
+            diagnostics::warning(format!("journal \"{}\" is not in the journal lists", j));
+        }
+    }
+    for f in bib.iter_mut().flat_map(|b| b.fields.iter_mut()) {
+        if f.name.eq_ignore_ascii_case("pages") {
+            if let Some(p) = Pages::parse(bibtex::unquote(&f.value)) {
+                f.value = format!("{{{}}}", p.format(opts.pages));
+            }
+        }
+    }
+    if opts.unicode || opts.ascii_tex {
+        let convert = if opts.unicode {
+            unicode::tex_to_unicode
         } else {
-            println!("{}", formatted);
+            unicode::to_latex
+        };
+        for f in bib.iter_mut().flat_map(|b| b.fields.iter_mut()) {
+            if !import::is_verbatim(&f.name) {
+                f.value = format!("{{{}}}", convert(bibtex::unquote(&f.value)));
+            }
         }
+    }
+    report_missing(&bib, opts);
+    if opts.sort {
+        // most recent first, papers of the same year by first page
+        bib.sort_by_cached_key(|b| {
+            (
+                std::cmp::Reverse(year(b).and_then(|y| y.parse::<usize>().ok())),
+                b.get("pages")
+                    .and_then(Pages::parse)
+                    .and_then(|p| p.number()),
+            )
+        });
+    }
+    Some(bib)
+}
+
+pub fn format_thebibliography(bib: Vec<Entry>, opts: &CompileOptions) -> String {
+    let size = utils::thebibliography_size(bib.len());
+    let mut formatted = header(opts);
+    if opts.aslist {
+        formatted.push_str("\\begin{enumerate}\n");
+    } else {
+        formatted.push_str(&format!("\\begin{{thebibliography}}{{{size}}}\n\n"));
+    }
+    for (n, b) in bib.into_iter().enumerate() {
+        let citename = format!("{}{}", opts.cite_prefix, b.key);
+        let bibkey = if opts.aslist {
+            format!("\\item[({})] ", n + 1)
+        } else {
+            format!("\\bibitem{{{}}}", citename)
+        };
+        formatted.push_str(&format!(
+            "{}\n\n",
+            utils::clean_bib_text(&format!(
+                "{} {}{}",
+                bibkey,
+                format_entry(&b, opts),
+                format_extras(&b, opts)
+            ))
+        ));
+    }
+    if opts.aslist {
+        formatted.push_str("\\end{enumerate}");
     } else {
-        println!("ERROR: Unable to read file \"{}\"", &cli.file);
+        formatted.push_str("\\end{thebibliography}");
     }
+    formatted
 }
 
-fn format_volume(b: &Entry) -> String {
-    let mut vol: Vec<&str> = vec![];
-    if let Some(tmp) = b.params.get("pages") {
-        vol.push(&tmp);
+/// Comment listing the packages needed by the enabled extra fields, empty when there are none.
+fn header(opts: &CompileOptions) -> String {
+    let mut packages = vec![];
+    match opts.doi {
+        Some(DoiStyle::Href) => packages.push("hyperref (\\href)"),
+        Some(DoiStyle::Command) => packages.push("doi (\\doi)"),
+        None => {}
     }
-    if let Some(tmp) = b.params.get("number") {
-        vol.push(&tmp);
+    if opts.url {
+        packages.push("url or hyperref (\\url)");
     }
-    if let Some(tmp) = b.params.get("volume") {
-        vol.push(&tmp);
+    if packages.is_empty() {
+        return String::new();
     }
-    let mut vol_fmt = "".to_owned();
-    if vol.len() > 0 {
-        let v = vol.pop().unwrap();
-        vol_fmt.push_str(&format!("\\textbf{{{v}}}"));
+    format!(
+        "% Generated by latex-thebib, requires the packages: {}\n",
+        packages.join(", ")
+    )
+}
+
+/// Note, ISBN, DOI, URL and arXiv identifier, each enabled by its own option.
+fn format_extras(b: &Entry, opts: &CompileOptions) -> String {
+    let mut extras = vec![];
+    if opts.note {
+        if let Some(n) = b.get("note") {
+            extras.push(n.to_owned());
+        }
     }
-    if vol.len() > 0 {
-        let v = vol.pop().unwrap();
-        vol_fmt.push_str(&format!("({v})"));
+    if opts.isbn {
+        if let Some(i) = b.get("isbn") {
+            extras.push(format!("ISBN {}", i));
+        }
     }
-    if vol.len() > 0 {
-        let v = vol.pop().unwrap();
-        vol_fmt.push_str(&format!(":{v}"));
+    if let (Some(style), Some(d)) = (opts.doi, b.get("doi")) {
+        let d = utils::bare_doi(d);
+        extras.push(match style {
+            DoiStyle::Href => format!(
+                "\\href{{https://doi.org/{}}}{{doi:{}}}",
+                d.replace('%', "\\%"),
+                d.replace('_', "\\_")
+                    .replace('%', "\\%")
+                    .replace('&', "\\&")
+            ),
+            DoiStyle::Command => format!("\\doi{{{}}}", d),
+        });
     }
-    return vol_fmt;
+    if opts.url {
+        if let Some(u) = b.get("url") {
+            extras.push(format!("\\url{{{}}}", utils::verbatim(u)));
+        }
+    }
+    if opts.eprint {
+        if let Some(e) = b.get("eprint") {
+            let prefix = b
+                .get("archiveprefix")
+                .or_else(|| b.get("eprinttype"))
+                .unwrap_or("arXiv");
+            let mut id = if prefix.eq_ignore_ascii_case("arxiv") {
+                format!("arXiv:{}", e)
+            } else {
+                format!("{}:{}", prefix, e)
+            };
+            if let Some(class) = b.get("primaryclass").or_else(|| b.get("eprintclass")) {
+                id.push_str(&format!(" [{}]", class));
+            }
+            extras.push(id);
+        }
+    }
+    extras.iter().map(|e| format!(", {}", e)).collect()
+}
+
+fn web_items(bib: &[Entry], opts: &CompileOptions) -> Vec<web::WebItem> {
+    bib.iter()
+        .map(|b| web::WebItem {
+            id: format!("{}{}", opts.cite_prefix, b.key),
+            tex: utils::clean_bib_text(&format_entry(b, opts)),
+            doi: b.get("doi").map(utils::bare_doi),
+            url: b.get("url").map(utils::verbatim),
+        })
+        .collect()
+}
+
+/// Entries given to `--only`, and the crossref parents that at least `--min-crossrefs` of them refer to.
+fn select(bib: Vec<Entry>, only_keys: &[String], min_crossrefs: usize) -> Vec<Entry> {
+    let only = only_keys
+        .iter()
+        .map(|k| k.to_lowercase())
+        .collect::<HashSet<String>>();
+    for k in only_keys.iter() {
+        if !bib.iter().any(|e| e.key.eq_ignore_ascii_case(k)) {
+            diagnostics::warning(format!("entry \"{}\" not found", k));
+        }
+    }
+    let counts =
+        crossref::crossref_counts(bib.iter().filter(|e| only.contains(&e.key.to_lowercase())));
+    bib.into_iter()
+        .filter(|e| {
+            let key = e.key.to_lowercase();
+            only.contains(&key) || counts.get(&key).is_some_and(|n| *n >= min_crossrefs)
+        })
+        .collect()
+}
+
+/// Year of an entry, or of its biblatex `date`.
+fn year(b: &Entry) -> Option<&str> {
+    b.get("year")
+        .or_else(|| b.get("date").and_then(|d| d.get(..4)))
+        .filter(|y| !y.is_empty())
+}
+
+/// Warns on stderr about entries missing the required fields of their type, and writes
+/// them to the `--report` file.
+fn report_missing(bib: &[Entry], opts: &CompileOptions) {
+    let mut items = vec![];
+    for e in bib {
+        let missing = e.missing_fields();
+        if missing.is_empty() {
+            continue;
+        }
+        diagnostics::warning(format!(
+            "entry \"{}\" (@{}) is missing required fields: {}",
+            e.key,
+            e.kind.to_lowercase(),
+            missing.join(", ")
+        ));
+        let fields = missing
+            .iter()
+            .map(|m| utils::json_string(m))
+            .collect::<Vec<String>>();
+        items.push(format!(
+            "  {{\"key\": {}, \"type\": {}, \"missing\": [{}]}}",
+            utils::json_string(&e.key),
+            utils::json_string(&e.kind.to_lowercase()),
+            fields.join(", ")
+        ));
+    }
+    if let Some(fname) = &opts.report {
+        let report = if items.is_empty() {
+            "[]\n".to_owned()
+        } else {
+            format!("[\n{}\n]\n", items.join(",\n"))
+        };
+        utils::write_file(fname.to_owned(), &report);
+    }
+}
+
+/// Text of a `bibitem`: authors, title, journal, volume, publisher and year.
+fn format_entry(b: &Entry, opts: &CompileOptions) -> String {
+    let authors = match (b.get("author"), b.get("editor")) {
+        (Some(a), _) => format_all_author(a),
+        (None, Some(e)) => {
+            let suffix = if names::split_names(e).len() > 1 {
+                "eds."
+            } else {
+                "ed."
+            };
+            format!("{} ({})", format_all_author(e), suffix)
+        }
+        (None, None) => "".to_owned(),
+    };
+    let t = match b.get("title") {
+        Some(t) => format!("\\textit{{{}}},", t),
+        None => "".to_owned(),
+    };
+    let y = format!("({})", year(b).unwrap_or("n.d."));
+
+    let p = if let Some(tmp) = &b.get("publisher") {
+        format!("- {}", tmp)
+    } else {
+        "".to_owned()
+    };
+
+    let j = b
+        .get("journal")
+        .or_else(|| b.get("booktitle"))
+        .unwrap_or("");
+
+    let vol_fmt = format_volume(b, &opts.volume_format);
+
+    let mut elements: Vec<&str> = vec![&authors, &t, j, &vol_fmt];
+    if opts.publisher {
+        elements.push(&p);
+    }
+    elements.push(&y);
+    elements.join(" ")
+}
+
+fn format_volume(b: &Entry, pattern: &Pattern) -> String {
+    pattern.format(&|f| {
+        let name = match f {
+            'v' => "volume",
+            'n' => "number",
+            _ => "pages",
+        };
+        b.get(name).filter(|v| !v.is_empty()).map(|v| v.to_owned())
+    })
 }
 
 fn format_all_author(a: &str) -> String {
@@ -140,27 +503,25 @@ fn format_all_author(a: &str) -> String {
         .map(|s| s.split(", ").collect())
         .map(format_author)
         .collect::<Vec<String>>();
-    if authors.len() == 0 {
+    if authors.is_empty() {
         return "".to_owned();
     } else if authors.len() == 1 {
         return format!("\\textsc{{{}}}", authors[0]);
     }
     let a1 = &authors[0..authors.len() - 1].join(", ");
     let a2 = &authors[authors.len() - 1];
-    return format!("\\textsc{{{} \\& {}}}", a1, a2);
+    format!("\\textsc{{{} \\& {}}}", a1, a2)
 }
 
 fn format_author(auth: Vec<&str>) -> String {
-    if auth.len() == 0 {
+    if auth.is_empty() {
         return "".to_owned();
     }
     let mut fmt_auth = auth[0].to_owned();
     if auth.len() > 1 {
         let proc = auth[1]
-            .split(" ")
-            .map(|s| {
-                return s[0..1].to_ascii_uppercase();
-            })
+            .split_whitespace()
+            .map(initial)
             .collect::<Vec<String>>()
             .join(". ");
         fmt_auth = format!("{}. {}", proc, fmt_auth);
@@ -168,95 +529,15 @@ fn format_author(auth: Vec<&str>) -> String {
     fmt_auth
 }
 
-fn find_closing_token(sub: &str, tok: u8, at: isize) -> Option<usize> {
-    let mut l: isize = 0;
-    for (i, &c) in sub.as_bytes().into_iter().enumerate() {
-        if l == at && c == tok {
-            return Some(i);
-        }
-        if c == b'{' {
-            l += 1;
-        }
-        if c == b'}' {
-            l -= 1;
-        }
-    }
-    return None;
-}
-
-fn get_bibentry_raw(data: &str) -> Option<(&str, &str, &str, &str)> {
-    let max_value = usize::max_value();
-    let max_len: usize = 100;
-    let o = data.find("@");
-    if o.is_none() {
-        return None;
-    }
-    let mut rest = &data[o.unwrap() + 1..];
-
-    let o = rest.find("{").unwrap_or(max_value);
-    if o == max_value {
-        let lim = rest.bytes().len().min(max_len);
-        println!("{}", &rest[0..lim]);
-        panic!("Parse error: found \"@\", and expected to find closing brace after entry type.")
-    }
-    let etype = &rest[0..o].trim();
-    rest = &rest[o + 1..];
-
-    let o = rest.find(",").unwrap_or(max_value);
-
-    if o == max_value {
-        let lim = rest.bytes().len().min(max_len);
-        println!("{} {}", etype, &rest[0..lim]);
-        panic!(
-            "Parse error: found opening brace, and expected to find \",\" after article keyname."
-        )
-    }
-    let keyname = &rest[0..o].trim();
-    rest = &rest[o + 1..];
-    let o = find_closing_token(rest, b'}', 0).unwrap_or(max_value);
-    if o == max_value {
-        let lim = rest.bytes().len().min(max_len);
-        println!("{} {} {}", etype, keyname, &rest[0..lim]);
-        panic!("Parse error: expected final closing brace for bibitem.")
-    }
-    let fields = &rest[0..o].trim();
-    rest = &rest[o + 1..];
-
-    return Some((rest, etype, keyname, fields));
-}
-
-fn parse_bibliography<'a>(data: &str) -> Vec<Entry> {
-    let mut entries = vec![];
-    let mut sub = data;
-
-    while let Some((rest, _, keyname, fields_s)) = get_bibentry_raw(sub) {
-        let mut entry = Entry {
-            name: keyname,
-            params: StrMap::new(),
-        };
-        sub = rest;
-        let mut fields = fields_s;
-        while let Some(e) = fields.find("=") {
-            let fieldname = &fields[0..e].trim();
-            fields = &fields[e + 1..];
-            let f = find_closing_token(fields, b',', 0).unwrap_or(fields.len());
-            let value = utils::trim_braces(&fields[0..f].trim());
-            if f < fields.len() {
-                fields = &fields[f + 1..];
-            }
-            let _ = entry.params.insert(fieldname, value);
-        }
-        if !entry.params.contains_key("year")
-            || !entry.params.contains_key("title")
-            || !entry.params.contains_key("author")
-        {
-            println!(
-                "%% Entry error: entry \"{}\" is missing fundamental fields (author, title, year).",
-                entry.name
-            );
-            continue;
+/// First letter of a given name, a leading `{\L}` or `{\"O}` group counts as one letter.
+fn initial(name: &str) -> String {
+    if name.starts_with('{') {
+        if let Some((g, _)) = thebibliography::group(name, b'{', b'}') {
+            return format!("{{{}}}", g);
         }
-        entries.push(entry);
     }
-    return entries;
+    name.chars()
+        .next()
+        .map(|c| c.to_uppercase().collect())
+        .unwrap_or_default()
 }
@@ -0,0 +1,101 @@
+//! # crossref
+//! BibTeX `crossref` and biblatex `xdata` inheritance.
+//!
+//! An entry inherits the fields it does not have from the entry named by its
+//! `crossref` field, and from the `@xdata` entries listed in its `xdata`
+//! field. The `title` of a book or proceedings parent becomes the `booktitle`
+//! of its children. Chains of references are followed, `@xdata` entries are
+//! only used for inheritance and never compiled.
+use crate::bibtex::{Entry, Field};
+use crate::diagnostics;
+use std::collections::HashMap;
+
+/// Fields that name other entries, never inherited.
+const NOT_INHERITED: [&str; 4] = ["crossref", "xdata", "ids", "title"];
+
+/// Parent entry types whose title is the booktitle of their children.
+const CONTAINERS: [&str; 6] = [
+    "book",
+    "mvbook",
+    "proceedings",
+    "mvproceedings",
+    "collection",
+    "mvcollection",
+];
+
+/// Entries with their inherited fields, `@xdata` entries removed.
+pub fn resolve(entries: &[Entry]) -> Vec<Entry> {
+    let index = entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (e.key.to_lowercase(), i))
+        .collect::<HashMap<String, usize>>();
+    (0..entries.len())
+        .filter(|i| !entries[*i].kind.eq_ignore_ascii_case("xdata"))
+        .map(|i| inherit(i, entries, &index, &mut vec![]))
+        .collect()
+}
+
+fn inherit(
+    i: usize,
+    entries: &[Entry],
+    index: &HashMap<String, usize>,
+    chain: &mut Vec<usize>,
+) -> Entry {
+    let mut e = entries[i].clone();
+    if chain.contains(&i) {
+        diagnostics::warning(format!(
+            "entry \"{}\" refers to itself through crossref or xdata",
+            e.key
+        ));
+        return e;
+    }
+    chain.push(i);
+    let mut parents: Vec<&str> = vec![];
+    if let Some(x) = entries[i].get("xdata") {
+        parents.extend(x.split(',').map(|k| k.trim()).filter(|k| !k.is_empty()));
+    }
+    if let Some(c) = entries[i].get("crossref") {
+        parents.push(c.trim());
+    }
+    for key in parents {
+        let Some(&p) = index.get(&key.to_lowercase()) else {
+            diagnostics::warning(format!(
+                "entry \"{}\" refers to the missing entry \"{}\"",
+                e.key, key
+            ));
+            continue;
+        };
+        let parent = inherit(p, entries, index, chain);
+        let container = CONTAINERS
+            .iter()
+            .any(|c| parent.kind.eq_ignore_ascii_case(c));
+        if container && e.field("booktitle").is_none() {
+            if let Some(t) = parent.field("title") {
+                e.fields.push(Field {
+                    name: "booktitle".to_owned(),
+                    value: t.value.to_owned(),
+                });
+            }
+        }
+        for f in parent.fields {
+            let inherited = !NOT_INHERITED.iter().any(|n| f.name.eq_ignore_ascii_case(n));
+            if inherited && e.field(&f.name).is_none() {
+                e.fields.push(f);
+            }
+        }
+    }
+    chain.pop();
+    e
+}
+
+/// Number of entries referring to each key through `crossref`, keys lowercase.
+pub fn crossref_counts<'a>(entries: impl Iterator<Item = &'a Entry>) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for e in entries {
+        if let Some(c) = e.get("crossref") {
+            *counts.entry(c.trim().to_lowercase()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
@@ -0,0 +1,158 @@
+//! # csl
+//! CSL-JSON output, the bibliography format read by citeproc processors
+//! such as Pandoc.
+//!
+//! Entry types and fields are mapped to their CSL counterparts, names are
+//! split into family/given parts, and LaTeX accents and markup are converted
+//! to plain Unicode text.
+use crate::bibtex::{self, Entry};
+use crate::names::{self, Name};
+use crate::unicode;
+use crate::utils::{self, json_string};
+
+/// CSL variable and the BibTeX fields it is taken from, first one found wins.
+const TEXT_FIELDS: [(&str, &[&str]); 13] = [
+    ("title", &["title"]),
+    ("container-title", &["journal", "journaltitle", "booktitle"]),
+    ("collection-title", &["series"]),
+    ("volume", &["volume"]),
+    ("chapter-number", &["chapter"]),
+    ("edition", &["edition"]),
+    (
+        "publisher",
+        &["publisher", "school", "institution", "organization"],
+    ),
+    ("publisher-place", &["address", "location"]),
+    ("genre", &["type"]),
+    ("note", &["note"]),
+    ("abstract", &["abstract"]),
+    ("ISBN", &["isbn"]),
+    ("ISSN", &["issn"]),
+];
+
+/// Writes the entries as a CSL-JSON array, `prefix` is prepended to each id.
+pub fn write_csl(entries: &[Entry], prefix: &str) -> String {
+    let items = entries
+        .iter()
+        .map(|e| item(e, &format!("{}{}", prefix, e.key)))
+        .collect::<Vec<String>>();
+    format!("[\n{}\n]", items.join(",\n"))
+}
+
+fn csl_type(kind: &str) -> &'static str {
+    match kind.to_lowercase().as_str() {
+        "article" => "article-journal",
+        "book" | "proceedings" | "mvbook" | "collection" => "book",
+        "booklet" => "pamphlet",
+        "inbook" | "incollection" | "bookinbook" => "chapter",
+        "inproceedings" | "conference" => "paper-conference",
+        "mastersthesis" | "phdthesis" | "thesis" => "thesis",
+        "techreport" | "report" | "manual" => "report",
+        "unpublished" => "manuscript",
+        "online" | "electronic" | "www" => "webpage",
+        "patent" => "patent",
+        "dataset" => "dataset",
+        "software" => "software",
+        _ => "document",
+    }
+}
+
+fn item(e: &Entry, id: &str) -> String {
+    let kind = csl_type(&e.kind);
+    let mut fields = vec![("id", json_string(id)), ("type", json_string(kind))];
+    for role in ["author", "editor"] {
+        if let Some(v) = e.get(role) {
+            fields.push((role, name_list(&names::parse_names(v))));
+        }
+    }
+    for (var, sources) in TEXT_FIELDS.iter() {
+        if let Some(v) = sources.iter().find_map(|s| e.get(s)) {
+            fields.push((var, json_string(&unicode::to_unicode(v))));
+        }
+    }
+    if let Some(v) = e.get("number") {
+        let var = if kind == "report" { "number" } else { "issue" };
+        fields.push((var, json_string(&unicode::to_unicode(v))));
+    }
+    if let Some(v) = e.get("pages") {
+        let pages = unicode::to_unicode(&v.replace("--", "-"));
+        fields.push(("page", json_string(&pages)));
+    }
+    if e.get("type").is_none() {
+        match e.kind.to_lowercase().as_str() {
+            "phdthesis" => fields.push(("genre", json_string("PhD thesis"))),
+            "mastersthesis" => fields.push(("genre", json_string("Master's thesis"))),
+            _ => {}
+        }
+    }
+    if let Some(v) = e.get("doi") {
+        fields.push(("DOI", json_string(&utils::bare_doi(v))));
+    }
+    if let Some(v) = e.get("url") {
+        fields.push(("URL", json_string(&utils::verbatim(v))));
+    }
+    if let Some(issued) = issued(e) {
+        fields.push(("issued", issued));
+    }
+    let body = fields
+        .iter()
+        .map(|(k, v)| format!("    {}: {}", json_string(k), v))
+        .collect::<Vec<String>>()
+        .join(",\n");
+    format!("  {{\n{}\n  }}", body)
+}
+
+fn name_list(names: &[Name]) -> String {
+    let names = names
+        .iter()
+        .map(|n| {
+            if n.literal {
+                return format!(
+                    "{{\"literal\": {}}}",
+                    json_string(&unicode::to_unicode(&n.last))
+                );
+            }
+            let parts = [
+                ("family", &n.last),
+                ("given", &n.first),
+                ("non-dropping-particle", &n.von),
+                ("suffix", &n.jr),
+            ];
+            let parts = parts
+                .iter()
+                .filter(|(_, v)| !v.is_empty())
+                .map(|(k, v)| {
+                    format!(
+                        "{}: {}",
+                        json_string(k),
+                        json_string(&unicode::to_unicode(v))
+                    )
+                })
+                .collect::<Vec<String>>();
+            format!("{{{}}}", parts.join(", "))
+        })
+        .collect::<Vec<String>>();
+    format!("[\n      {}\n    ]", names.join(",\n      "))
+}
+
+/// `issued` date from `year` and `month`, or from a biblatex `date` such as `2020-05-12`.
+fn issued(e: &Entry) -> Option<String> {
+    let mut parts = vec![];
+    if let Some(year) = e.get("year") {
+        let year = unicode::to_unicode(year);
+        if year.trim().parse::<i32>().is_err() {
+            return Some(format!("{{\"literal\": {}}}", json_string(&year)));
+        }
+        parts.push(year.trim().to_owned());
+        if let Some(m) = e.get("month").and_then(bibtex::month_number) {
+            parts.push(m.to_string());
+        }
+    } else {
+        let date = e.get("date")?;
+        let date = date.split('/').next().unwrap_or_default();
+        for p in date.split('-').take(3) {
+            parts.push(p.trim().parse::<i32>().ok()?.to_string());
+        }
+    }
+    Some(format!("{{\"date-parts\": [[{}]]}}", parts.join(", ")))
+}
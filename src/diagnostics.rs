@@ -0,0 +1,140 @@
+//! # diagnostics
+//! Errors, warnings and progress messages, always written to stderr so that
+//! output printed to stdout stays clean.
+//!
+//! `--quiet` keeps only errors, `--verbose` adds progress messages, and
+//! `--diagnostics-json` also records every message, whatever the level, in
+//! a JSON file written when the command ends. Any error makes the command
+//! exit with a failure status.
+use crate::utils::{self, json_string};
+use clap::Args;
+use std::process::ExitCode;
+use std::sync::Mutex;
+
+#[derive(Args)]
+pub struct DiagnosticsCli {
+    #[arg(short, long, default_value_t = false, conflicts_with = "verbose")]
+    /// only report errors
+    quiet: bool,
+    #[arg(short, long, default_value_t = false)]
+    /// also report progress, such as replaced duplicates and written files
+    verbose: bool,
+    #[arg(long)]
+    /// write all the diagnostics to this JSON file
+    diagnostics_json: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Level {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Level {
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Info => "info",
+        }
+    }
+}
+
+struct Sink {
+    /// Most detailed level written to stderr.
+    shown: Level,
+    json: Option<String>,
+    records: Vec<(Level, String)>,
+    /// An error was reported since the last reset.
+    failed: bool,
+}
+
+static SINK: Mutex<Sink> = Mutex::new(Sink {
+    shown: Level::Warning,
+    json: None,
+    records: Vec::new(),
+    failed: false,
+});
+
+/// Sets up the levels and the JSON file, before the command runs.
+pub fn init(cli: &DiagnosticsCli) {
+    let mut sink = SINK.lock().unwrap();
+    sink.shown = if cli.quiet {
+        Level::Error
+    } else if cli.verbose {
+        Level::Info
+    } else {
+        Level::Warning
+    };
+    sink.json = cli.diagnostics_json.to_owned();
+}
+
+/// Forgets the messages of a previous run, before running a command again.
+pub fn reset() {
+    let mut sink = SINK.lock().unwrap();
+    sink.records.clear();
+    sink.failed = false;
+}
+
+fn report(level: Level, msg: String) {
+    let mut sink = SINK.lock().unwrap();
+    if level == Level::Error {
+        sink.failed = true;
+    }
+    if level <= sink.shown {
+        match level {
+            Level::Error => eprintln!("ERROR: {}", msg),
+            _ => eprintln!("{}: {}", level.name(), msg),
+        }
+    }
+    if sink.json.is_some() {
+        sink.records.push((level, msg));
+    }
+}
+
+pub fn error(msg: impl Into<String>) {
+    report(Level::Error, msg.into());
+}
+
+pub fn warning(msg: impl Into<String>) {
+    report(Level::Warning, msg.into());
+}
+
+pub fn info(msg: impl Into<String>) {
+    report(Level::Info, msg.into());
+}
+
+/// Writes the JSON file, once the command is over.
+pub fn finish() {
+    let sink = SINK.lock().unwrap();
+    let Some(fname) = &sink.json else {
+        return;
+    };
+    let items = sink
+        .records
+        .iter()
+        .map(|(level, msg)| {
+            format!(
+                "  {{\"level\": {}, \"message\": {}}}",
+                json_string(level.name()),
+                json_string(msg)
+            )
+        })
+        .collect::<Vec<String>>();
+    let json = if items.is_empty() {
+        "[]\n".to_owned()
+    } else {
+        format!("[\n{}\n]\n", items.join(",\n"))
+    };
+    utils::write_file(fname.to_owned(), &json);
+}
+
+/// Exit status of the command: a failure if any error was reported.
+pub fn exit_code() -> ExitCode {
+    if SINK.lock().unwrap().failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
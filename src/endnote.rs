@@ -0,0 +1,362 @@
+//! # endnote
+//! EndNote XML reader, the `<xml><records><record>...` export of EndNote.
+//!
+//! Only the small subset of XML used by these exports is understood:
+//! elements, attributes, text, entities, comments and CDATA sections.
+use crate::import::Record;
+
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find_map(|c| match c {
+            Node::Element(e) if e.name == name => Some(e),
+            _ => None,
+        })
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter_map(move |c| match c {
+            Node::Element(e) if e.name == name => Some(e),
+            _ => None,
+        })
+    }
+
+    /// Text of the element and its descendants, `<style>` wrappers included.
+    fn text(&self) -> String {
+        self.children
+            .iter()
+            .map(|c| match c {
+                Node::Element(e) => e.text(),
+                Node::Text(t) => t.to_owned(),
+            })
+            .collect()
+    }
+
+    fn path(&self, path: &[&str]) -> Option<&Element> {
+        path.iter().try_fold(self, |e, name| e.child(name))
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn entry_type(ref_type: &Element) -> &'static str {
+    let name = ref_type.attr("name").unwrap_or_default();
+    match (name, ref_type.text().trim()) {
+        ("Journal Article", _) | (_, "17") => "article",
+        ("Book Section", _) | (_, "5") => "incollection",
+        ("Book" | "Edited Book", _) | (_, "6" | "28") => "book",
+        ("Conference Proceedings" | "Conference Paper", _) | (_, "10" | "47") => "inproceedings",
+        ("Thesis", _) | (_, "32") => "phdthesis",
+        ("Report", _) | (_, "27") => "techreport",
+        ("Unpublished Work", _) | (_, "34") => "unpublished",
+        ("Web Page", _) | (_, "12") => "online",
+        _ => "misc",
+    }
+}
+
+pub fn parse(data: &str) -> Result<Vec<Record>, String> {
+    let mut p = Parser { data, pos: 0 };
+    let root = p.document()?;
+    let mut records = vec![];
+    let mut stack = vec![&root];
+    // records may be nested in <xml><records>, or be the root itself
+    while let Some(e) = stack.pop() {
+        if e.name == "record" {
+            records.push(record(e));
+            continue;
+        }
+        for c in e.children.iter().rev() {
+            if let Node::Element(c) = c {
+                stack.push(c);
+            }
+        }
+    }
+    Ok(records)
+}
+
+fn record(e: &Element) -> Record {
+    let mut r = Record {
+        kind: e
+            .child("ref-type")
+            .map(entry_type)
+            .unwrap_or("misc")
+            .to_owned(),
+        ..Default::default()
+    };
+    let text = |path: &[&str]| e.path(path).map(|e| e.text());
+    if let Some(authors) = e.path(&["contributors", "authors"]) {
+        for a in authors.children("author") {
+            r.add("author", &a.text());
+        }
+    }
+    if let Some(editors) = e.path(&["contributors", "secondary-authors"]) {
+        for a in editors.children("author") {
+            r.add("editor", &a.text());
+        }
+    }
+    if let Some(t) = text(&["titles", "title"]) {
+        r.add("title", &t);
+    }
+    let container = text(&["titles", "secondary-title"])
+        .or_else(|| text(&["periodical", "full-title"]))
+        .or_else(|| text(&["periodical", "abbr-1"]));
+    if let Some(c) = container {
+        let field = match r.kind.as_str() {
+            "incollection" | "inproceedings" => "booktitle",
+            "article" => "journal",
+            _ => "series",
+        };
+        r.add(field, &c);
+    }
+    if let Some(t) = text(&["titles", "tertiary-title"]) {
+        r.add("series", &t);
+    }
+    if let Some(y) = text(&["dates", "year"]) {
+        r.add_date(&y);
+    }
+    if let Some(m) = text(&["dates", "pub-dates", "date"]) {
+        if let Some(month) = m.split_whitespace().next() {
+            r.add("month", month);
+        }
+    }
+    if let Some(p) = text(&["pages"]) {
+        let p = p
+            .split(['-', '–'])
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<&str>>();
+        r.add("pages", &p.join("--"));
+    }
+    let publisher = match r.kind.as_str() {
+        "phdthesis" => "school",
+        "techreport" => "institution",
+        _ => "publisher",
+    };
+    let simple = [
+        ("volume", "volume"),
+        ("number", "number"),
+        ("publisher", publisher),
+        ("pub-location", "address"),
+        ("edition", "edition"),
+        ("electronic-resource-num", "doi"),
+        ("abstract", "abstract"),
+        ("notes", "note"),
+        ("language", "language"),
+    ];
+    for (tag, field) in simple {
+        if let Some(v) = text(&[tag]) {
+            r.add(field, &v);
+        }
+    }
+    if let Some(v) = text(&["isbn"]) {
+        r.add(if r.kind == "article" { "issn" } else { "isbn" }, &v);
+    }
+    if let Some(urls) = e.path(&["urls", "related-urls"]) {
+        if let Some(u) = urls.children("url").next() {
+            r.add("url", &u.text());
+        }
+    }
+    if let Some(kws) = e.child("keywords") {
+        for k in kws.children("keyword") {
+            r.add("keywords", &k.text());
+        }
+    }
+    r
+}
+
+struct Parser<'a> {
+    data: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> String {
+        let line = self.data[..self.pos].matches('\n').count() + 1;
+        format!("Parse error at line {}: {}", line, msg)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.data[self.pos..]
+    }
+
+    /// Skips the prolog, comments and doctype, returns the root element.
+    fn document(&mut self) -> Result<Element, String> {
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with('<') {
+                return self.element();
+            }
+            if self.rest().is_empty() {
+                return Err(self.error("no root element"));
+            }
+            self.pos += self.rest().chars().next().unwrap().len_utf8();
+        }
+    }
+
+    fn skip_to(&mut self, end: &str) -> Result<(), String> {
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("missing \"{}\"", end))),
+        }
+    }
+
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            let rest = self.rest().trim_start();
+            self.pos = self.data.len() - rest.len();
+            if rest.starts_with("<?") {
+                self.skip_to("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_to("-->")?;
+            } else if rest.starts_with("<!") && !rest.starts_with("<![CDATA[") {
+                self.skip_to(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(rest.len());
+        self.pos += end;
+        rest[..end].to_owned()
+    }
+
+    fn element(&mut self) -> Result<Element, String> {
+        self.pos += 1;
+        let name = self.name();
+        let mut attrs = vec![];
+        loop {
+            let rest = self.rest().trim_start();
+            self.pos = self.data.len() - rest.len();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(Element {
+                    name,
+                    attrs,
+                    children: vec![],
+                });
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            if rest.is_empty() {
+                return Err(self.error(&format!("unterminated tag <{}>", name)));
+            }
+            let attr = self.name();
+            let rest = self.rest().trim_start();
+            self.pos = self.data.len() - rest.len();
+            let mut value = String::new();
+            if let Some(r) = rest.strip_prefix('=') {
+                let r = r.trim_start();
+                let Some(q) = r.chars().next().filter(|q| *q == '"' || *q == '\'') else {
+                    return Err(self.error("attribute value must be quoted"));
+                };
+                let Some(end) = r[1..].find(q) else {
+                    return Err(self.error("unterminated attribute value"));
+                };
+                value = unescape(&r[1..1 + end]);
+                self.pos = self.data.len() - r.len() + end + 2;
+            }
+            if attr.is_empty() {
+                return Err(self.error(&format!("malformed tag <{}>", name)));
+            }
+            attrs.push((attr, value));
+        }
+        let mut children = vec![];
+        loop {
+            let rest = self.rest();
+            if let Some(r) = rest.strip_prefix("</") {
+                let end = r.find('>').unwrap_or(r.len());
+                if r[..end].trim() != name {
+                    return Err(self.error(&format!(
+                        "expected </{}>, found </{}>",
+                        name,
+                        r[..end].trim()
+                    )));
+                }
+                self.pos += end + 3;
+                return Ok(Element {
+                    name,
+                    attrs,
+                    children,
+                });
+            } else if let Some(r) = rest.strip_prefix("<![CDATA[") {
+                let Some(end) = r.find("]]>") else {
+                    return Err(self.error("unterminated CDATA section"));
+                };
+                children.push(Node::Text(r[..end].to_owned()));
+                self.pos += "<![CDATA[".len() + end + 3;
+            } else if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc()?;
+            } else if rest.starts_with('<') {
+                children.push(Node::Element(self.element()?));
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("missing </{}>", name)));
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                children.push(Node::Text(unescape(&rest[..end])));
+                self.pos += end;
+            }
+        }
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            e if e.starts_with("#x") || e.starts_with("#X") => u32::from_str_radix(&e[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            e if e.starts_with('#') => e[1..].parse::<u32>().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
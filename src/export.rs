@@ -0,0 +1,144 @@
+//! # export
+//! Structured export of a refactored bibliography, for reference managers
+//! and other tools.
+//!
+//! Every item carries its key, the fields read from REVTeX `\bibinfo` markup
+//! or guessed from the text (authors, title, year), the raw text, and the
+//! keys of the duplicates it replaced.
+use crate::format;
+use crate::import::{self, Record};
+use crate::ris;
+use crate::thebibliography::BibEntry;
+use crate::unicode;
+use crate::utils::json_string;
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Export {
+    /// BibTeX database, replaced keys in the `ids` field
+    Bib,
+    /// RIS records
+    Ris,
+    /// JSON, with the raw text of each item and the replacements map
+    Json,
+}
+
+impl Export {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Export::Bib => "bib",
+            Export::Ris => "ris",
+            Export::Json => "json",
+        }
+    }
+}
+
+/// `replacements` maps each dropped duplicate key to the key that replaced it.
+pub fn export(items: &[BibEntry], replacements: &[(String, String)], format: Export) -> String {
+    match format {
+        Export::Bib => {
+            let blocks = import::into_blocks(records(items, replacements));
+            format::format_blocks(blocks, false)
+        }
+        Export::Ris => ris::write(&records(items, replacements)),
+        Export::Json => write_json(items, replacements),
+    }
+}
+
+fn replaced<'a>(key: &str, replacements: &'a [(String, String)]) -> Vec<&'a str> {
+    replacements
+        .iter()
+        .filter(|(_, new)| new == key)
+        .map(|(old, _)| old.as_str())
+        .collect()
+}
+
+/// Structured fields of an item, guessed from the text when it has no `\bibinfo` markup.
+fn fields(b: &BibEntry) -> Vec<(String, String)> {
+    if !b.fields.is_empty() {
+        return b.fields.clone();
+    }
+    let mut fields = vec![];
+    for (name, value) in [
+        ("author", b.authors_guess()),
+        ("title", b.title_guess()),
+        ("year", b.sort_year()),
+    ] {
+        if !value.is_empty() {
+            fields.push((name.to_owned(), value));
+        }
+    }
+    fields
+}
+
+fn records(items: &[BibEntry], replacements: &[(String, String)]) -> Vec<Record> {
+    items
+        .iter()
+        .map(|b| {
+            let mut r = Record {
+                kind: if b.field("journal").is_some() {
+                    "article".to_owned()
+                } else {
+                    "misc".to_owned()
+                },
+                key: Some(b.key.to_owned()),
+                fields: vec![],
+            };
+            for (name, value) in fields(b) {
+                r.add(&name, &value);
+            }
+            if b.fields.is_empty() {
+                r.add("note", &unicode::to_unicode(&b.text));
+            }
+            let ids = replaced(&b.key, replacements);
+            if !ids.is_empty() {
+                r.add("ids", &ids.join(", "));
+            }
+            r
+        })
+        .collect()
+}
+
+fn write_json(items: &[BibEntry], replacements: &[(String, String)]) -> String {
+    let items = items
+        .iter()
+        .map(|b| {
+            let fields = fields(b)
+                .iter()
+                .map(|(n, v)| format!("{}: {}", json_string(n), json_string(v)))
+                .collect::<Vec<String>>();
+            let replaces = replaced(&b.key, replacements)
+                .iter()
+                .map(|k| json_string(k))
+                .collect::<Vec<String>>();
+            let label = match &b.label {
+                Some(l) => json_string(l),
+                None => "null".to_owned(),
+            };
+            format!(
+                "    {{\n      \"key\": {},\n      \"label\": {},\n      \"text\": {},\n      \"fields\": {{{}}},\n      \"replaces\": [{}]\n    }}",
+                json_string(&b.key),
+                label,
+                json_string(&b.text),
+                fields.join(", "),
+                replaces.join(", ")
+            )
+        })
+        .collect::<Vec<String>>();
+    let map = replacements
+        .iter()
+        .map(|(old, new)| format!("    {}: {}", json_string(old), json_string(new)))
+        .collect::<Vec<String>>();
+    let block = |lines: Vec<String>, open: &str, close: &str| {
+        if lines.is_empty() {
+            format!("{}{}", open, close)
+        } else {
+            format!("{}\n{}\n  {}", open, lines.join(",\n"), close)
+        }
+    };
+    format!(
+        "{{\n  \"items\": {},\n  \"replacements\": {}\n}}\n",
+        block(items, "[", "]"),
+        block(map, "{", "}")
+    )
+}
@@ -0,0 +1,193 @@
+//! # format
+//! Rewrites a BibTeX database in canonical form.
+//!
+//! Entry types and field names are lowercased, fields are indented and
+//! ordered consistently, values are brace-delimited and entries are sorted
+//! by key. `@preamble`, `@string` and `@comment` blocks are kept. Formatting
+//! an already formatted database gives the same database.
+//!
+//! Run it as `latex-thebib format -f biblio.bib -o biblio.bib`.
+use crate::bibtex::Block;
+use crate::case;
+use crate::compile::DEF_OUTPUT;
+use crate::diagnostics;
+use crate::import;
+use crate::utils;
+use clap::Args;
+use std::collections::HashSet;
+
+#[derive(Args)]
+pub struct FormatCli {
+    #[arg(short, long)]
+    /// BibTeX, RIS or EndNote XML file
+    file: String,
+    #[arg(short, long, default_value = DEF_OUTPUT)]
+    /// Output BibTeX file name
+    output: String,
+    #[arg(short, long, default_value_t = false)]
+    /// keep entries in their original order instead of sorting them by key
+    keep_order: bool,
+    #[arg(short, long, default_value_t = false)]
+    /// warn about title words that BibTeX styles would lowercase, like acronyms and proper nouns
+    lint: bool,
+}
+
+pub fn run_format(cli: &FormatCli) {
+    let blocks = match import::read_blocks(&cli.file, false) {
+        Ok(blocks) => blocks,
+        Err(e) => {
+            diagnostics::error(e);
+            return;
+        }
+    };
+    report_duplicates(&blocks);
+    if cli.lint {
+        lint_titles(&blocks);
+    }
+    let formatted = format_blocks(blocks, !cli.keep_order);
+    if cli.output != DEF_OUTPUT {
+        utils::write_file(cli.output.to_owned(), &formatted);
+    } else {
+        print!("{}", formatted);
+    }
+}
+
+/// Preambles first, then string definitions, entries and comments, separated by blank lines.
+pub fn format_blocks(mut blocks: Vec<Block>, sort: bool) -> String {
+    let rank = |b: &Block| match b {
+        Block::Preamble(_) => 0,
+        Block::String(_) => 1,
+        Block::Entry(_) => 2,
+        Block::Comment(_) => 3,
+    };
+    if sort {
+        blocks.sort_by_cached_key(|b| match b {
+            Block::Entry(e) => (rank(b), e.key.to_lowercase()),
+            _ => (rank(b), "".to_owned()),
+        });
+    } else {
+        blocks.sort_by_key(rank);
+    }
+    blocks
+        .iter()
+        .map(|b| format!("{}\n", b))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn report_duplicates(blocks: &[Block]) {
+    let mut keys = HashSet::new();
+    for b in blocks {
+        if let Block::Entry(e) = b {
+            if !keys.insert(e.key.to_lowercase()) {
+                diagnostics::warning(format!("duplicate key \"{}\"", e.key));
+            }
+            for f in e.duplicate_fields() {
+                diagnostics::warning(format!(
+                    "entry \"{}\" has a duplicate field \"{}\", only the first one is kept",
+                    e.key, f
+                ));
+            }
+        }
+    }
+}
+
+fn lint_titles(blocks: &[Block]) {
+    for b in blocks {
+        if let Block::Entry(e) = b {
+            for w in case::unprotected_capitals(e.get("title").unwrap_or_default()) {
+                diagnostics::warning(format!("entry \"{}\": title word \"{}\" would be lowercased by BibTeX styles, protect it as {{{}}}",
+                    e.key, w, w));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bibtex::{self, Entry};
+    use std::collections::HashMap;
+
+    const DATABASE: &str = r#"% free text is dropped
+@String{acm = "ACM Press"}
+@Preamble{ "\newcommand{\noop}[1]{}" }
+
+@Article{smith2020,
+  Title = {The {Theory} of Everything},
+  AUTHOR = "Smith, John and Doe, Jane",
+  journal = {Phys. Rev.},
+  year = 2020,
+  pages = {100--110},
+  month = mar,
+}
+
+@inproceedings(doe2019,
+  author = {Doe, Jane},
+  title = "Quick " # {and} # " Dirty",
+  booktitle = {Proc. of the } # acm # { Conference},
+  year = {2019}
+)
+
+@comment{jabref-meta: databaseType:bibtex;}
+"#;
+
+    /// Entries as plain text, independently of field order and value delimiters.
+    fn content(blocks: &[Block]) -> Vec<String> {
+        let strings = HashMap::new();
+        let mut entries = bibtex::entries(blocks)
+            .iter()
+            .map(|e: &Entry| {
+                let mut fields = e
+                    .fields
+                    .iter()
+                    .map(|f| (f.name.to_lowercase(), bibtex::expand(&f.value, &strings)))
+                    .collect::<Vec<(String, String)>>();
+                fields.sort();
+                format!("{} {} {:?}", e.kind.to_lowercase(), e.key, fields)
+            })
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        let once = format_blocks(bibtex::parse(DATABASE).unwrap(), true);
+        let twice = format_blocks(bibtex::parse(&once).unwrap(), true);
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn format_keeps_entries() {
+        let original = bibtex::parse(DATABASE).unwrap();
+        let formatted = bibtex::parse(&format_blocks(original.clone(), true)).unwrap();
+        assert_eq!(content(&original), content(&formatted));
+        assert_eq!(original.len(), formatted.len());
+    }
+
+    #[test]
+    fn format_orders_blocks() {
+        let out = format_blocks(bibtex::parse(DATABASE).unwrap(), true);
+        let at = |s: &str| out.find(s).unwrap();
+        assert!(at("@preamble") < at("@string"));
+        assert!(at("@string") < at("@article{smith2020"));
+        assert!(at("@inproceedings{doe2019") < at("@article{smith2020"));
+        assert!(at("@article{smith2020") < at("@comment"));
+        assert!(out.contains(
+            "  author = {Smith, John and Doe, Jane},\n  title = {The {Theory} of Everything},"
+        ));
+    }
+
+    #[test]
+    fn format_keeps_spaces_of_concatenations() {
+        let out = format_blocks(bibtex::parse(DATABASE).unwrap(), true);
+        assert!(out.contains("  title = {Quick } # {and} # { Dirty},"));
+        let e = &bibtex::entries(&bibtex::parse(&out).unwrap())[0];
+        assert_eq!(e.get("title"), Some("Quick and Dirty"));
+        assert_eq!(
+            e.get("booktitle"),
+            Some("Proc. of the ACM Press Conference")
+        );
+    }
+}
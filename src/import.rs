@@ -0,0 +1,202 @@
+//! # import
+//! Reading of reference databases in any supported format: BibTeX, RIS and
+//! EndNote XML.
+//!
+//! The format is detected from the content of the file, falling back on its
+//! extension, and RIS/EndNote records are mapped onto BibTeX entries, so that
+//! every command can work on mixed sources.
+use crate::bibtex::{self, Block, Entry, Field};
+use crate::names;
+use crate::unicode;
+use crate::{endnote, ris, utils};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    BibTeX,
+    Ris,
+    EndNoteXml,
+}
+
+/// A reference read from a non-BibTeX source, with plain-text field values.
+#[derive(Default)]
+pub struct Record {
+    pub kind: String,
+    pub key: Option<String>,
+    pub fields: Vec<(String, String)>,
+}
+
+impl Record {
+    /// Adds a field; authors, editors and keywords accumulate, other fields keep their first value.
+    pub fn add(&mut self, name: &str, value: &str) {
+        let value = value.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if value.is_empty() {
+            return;
+        }
+        let sep = match name {
+            "author" | "editor" => " and ",
+            "keywords" => ", ",
+            _ => "",
+        };
+        match self.fields.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) if !sep.is_empty() => {
+                v.push_str(sep);
+                v.push_str(&value);
+            }
+            Some(_) => {}
+            None => self.fields.push((name.to_owned(), value)),
+        }
+    }
+
+    /// Continues the last value of a field, for values spanning several lines.
+    pub fn extend(&mut self, name: &str, value: &str) {
+        if value.trim().is_empty() {
+            return;
+        }
+        match self.fields.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => {
+                v.push(' ');
+                v.push_str(&value.split_whitespace().collect::<Vec<&str>>().join(" "));
+            }
+            None => self.add(name, value),
+        }
+    }
+
+    /// Splits a date such as `2020/05/12/` or `2020-05` into `year` and `month`.
+    pub fn add_date(&mut self, date: &str) {
+        let mut parts = date.split(['/', '-', ' ']).filter(|p| !p.is_empty());
+        if let Some(y) = parts.next() {
+            let y = y.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+            self.add("year", &y);
+        }
+        if let Some(m) = parts.next() {
+            self.add("month", m);
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub fn detect(fname: &str, data: &str) -> InputFormat {
+    let start = data.trim_start_matches('\u{feff}').trim_start();
+    if start.starts_with("TY  -") {
+        return InputFormat::Ris;
+    }
+    if start.starts_with("<?xml") || start.starts_with("<xml") || start.starts_with("<records") {
+        return InputFormat::EndNoteXml;
+    }
+    let ext = std::path::Path::new(fname)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match ext.as_str() {
+        "ris" => InputFormat::Ris,
+        "xml" => InputFormat::EndNoteXml,
+        _ => InputFormat::BibTeX,
+    }
+}
+
+/// Reads and parses a database, `strip_comments` removes TeX `%` comments from BibTeX files.
+pub fn read_blocks(fname: &str, strip_comments: bool) -> Result<Vec<Block>, String> {
+    let Ok(data) = std::fs::read_to_string(fname) else {
+        return Err(format!("Unable to read file \"{}\"", fname));
+    };
+    let parsed = match detect(fname, &data) {
+        InputFormat::BibTeX if strip_comments => {
+            bibtex::parse(&utils::read_tex_stripped(fname).unwrap_or(data))
+        }
+        InputFormat::BibTeX => bibtex::parse(&data),
+        InputFormat::Ris => Ok(into_blocks(ris::parse(&data))),
+        InputFormat::EndNoteXml => endnote::parse(&data).map(into_blocks),
+    };
+    parsed.map_err(|e| format!("{}: {}", fname, e))
+}
+
+/// BibTeX entries of the records, keys are made of the first author's surname and the year when missing.
+pub fn into_blocks(records: Vec<Record>) -> Vec<Block> {
+    let mut taken = HashSet::new();
+    let mut blocks = vec![];
+    for r in records {
+        let base = r.key.clone().unwrap_or_else(|| generate_key(&r));
+        let key = (0..)
+            .map(|n| match n {
+                0 => base.clone(),
+                n => format!("{}{}", base, utils::letter_suffix(n - 1)),
+            })
+            .find(|k| !taken.contains(&k.to_lowercase()))
+            .unwrap();
+        taken.insert(key.to_lowercase());
+        let fields = r
+            .fields
+            .iter()
+            .map(|(name, value)| Field {
+                name: name.to_owned(),
+                value: field_value(name, value),
+            })
+            .collect();
+        blocks.push(Block::Entry(Entry {
+            kind: r.kind,
+            key,
+            fields,
+        }));
+    }
+    blocks
+}
+
+fn generate_key(r: &Record) -> String {
+    let surname = r
+        .get("author")
+        .or(r.get("editor"))
+        .and_then(|a| names::parse_names(a).into_iter().next())
+        .map(|n| n.last)
+        .unwrap_or_default();
+    let surname = unicode::ascii_fold(&surname)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>();
+    let surname = if surname.is_empty() {
+        "ref".to_owned()
+    } else {
+        surname
+    };
+    format!("{}{}", surname, r.get("year").unwrap_or_default())
+}
+
+/// Fields holding identifiers rather than text, never escaped nor converted.
+pub fn is_verbatim(name: &str) -> bool {
+    ["url", "doi", "eprint", "ids"]
+        .iter()
+        .any(|n| name.eq_ignore_ascii_case(n))
+}
+
+/// Raw BibTeX value: months become macros, text is brace-delimited with TeX specials escaped.
+pub fn field_value(name: &str, value: &str) -> String {
+    if name == "month" {
+        if let Some(m) = bibtex::month_number(value) {
+            return bibtex::month_macro(m).to_owned();
+        }
+    }
+    let verbatim = is_verbatim(name);
+    let mut out = String::from("{");
+    for c in value.chars() {
+        match c {
+            '{' | '}' if verbatim => {}
+            '{' => out.push_str("\\textbraceleft{}"),
+            '}' => out.push_str("\\textbraceright{}"),
+            '&' | '%' | '$' | '#' | '_' if !verbatim => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\\' if !verbatim => out.push_str("\\textbackslash{}"),
+            c => out.push(c),
+        }
+    }
+    out.push('}');
+    out
+}
@@ -0,0 +1,227 @@
+//! # includes
+//! Builds the graph of `\input`/`\include` relations of a TeX document.
+//!
+//! Every file is visited once, repeated includes are recorded but not walked
+//! again, and include cycles are reported instead of recursing forever.
+//!
+//! Run it as `latex-thebib deps -f master.tex` to print the include tree.
+use crate::diagnostics;
+use crate::utils;
+use clap::Args;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct DepsCli {
+    #[arg(short, long)]
+    /// Master TeX file
+    file: String,
+}
+
+pub struct IncludeGraph {
+    root: String,
+    files: Vec<String>,
+    children: HashMap<String, Vec<String>>,
+}
+
+pub fn run_deps(cli: &DepsCli) {
+    match IncludeGraph::build(&cli.file) {
+        Ok(graph) => print!("{}", graph.tree()),
+        Err(e) => diagnostics::error(e),
+    }
+}
+
+impl IncludeGraph {
+    /// Walks the document starting from `root`, failing on missing files and include cycles.
+    pub fn build(root: &str) -> Result<IncludeGraph, String> {
+        Self::build_with(root, utils::read_tex_stripped)
+    }
+
+    /// Same as `build`, reading the stripped contents of the files with `read`.
+    pub fn build_with(
+        root: &str,
+        mut read: impl FnMut(&str) -> Option<String>,
+    ) -> Result<IncludeGraph, String> {
+        let mut graph = IncludeGraph {
+            root: root.to_owned(),
+            files: vec![],
+            children: HashMap::new(),
+        };
+        let mut done = HashSet::new();
+        let mut stack = vec![];
+        graph.visit(root, &mut read, &mut done, &mut stack)?;
+        Ok(graph)
+    }
+
+    fn visit(
+        &mut self,
+        fname: &str,
+        read: &mut impl FnMut(&str) -> Option<String>,
+        done: &mut HashSet<PathBuf>,
+        stack: &mut Vec<(PathBuf, String)>,
+    ) -> Result<(), String> {
+        let id = file_id(fname);
+        if let Some(pos) = stack.iter().position(|(p, _)| p == &id) {
+            let mut cycle = stack[pos..]
+                .iter()
+                .map(|(_, n)| n.to_owned())
+                .collect::<Vec<String>>();
+            cycle.push(fname.to_owned());
+            return Err(format!("include cycle: {}", cycle.join(" -> ")));
+        }
+        if !done.insert(id.clone()) {
+            return Ok(());
+        }
+        let contents = read(fname).ok_or_else(|| format!("file \"{}\" cannot be read", fname))?;
+        let mut children = vec![];
+        for c in include_names(&contents) {
+            let name = file_from_file(fname, &c)
+                .ok_or_else(|| format!("file \"{}\" included by \"{}\" not found", c, fname))?;
+            children.push(name);
+        }
+        self.files.push(fname.to_owned());
+        self.children.insert(fname.to_owned(), children.clone());
+        stack.push((id, fname.to_owned()));
+        for c in children.iter() {
+            self.visit(c, read, done, stack)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    /// All files of the document, each one once, in the order they are first included.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Renders the include tree, marking files that were already listed above.
+    pub fn tree(&self) -> String {
+        let mut out = format!("{}\n", self.root);
+        let mut seen = HashSet::from([file_id(&self.root)]);
+        self.tree_rec(&self.root, "", &mut seen, &mut out);
+        out
+    }
+
+    fn tree_rec(&self, fname: &str, indent: &str, seen: &mut HashSet<PathBuf>, out: &mut String) {
+        let children = self.children.get(fname).map(|v| &v[..]).unwrap_or(&[]);
+        for (i, c) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let branch = if last { "└── " } else { "├── " };
+            if seen.insert(file_id(c)) {
+                out.push_str(&format!("{}{}{}\n", indent, branch, c));
+                let next = format!("{}{}", indent, if last { "    " } else { "│   " });
+                self.tree_rec(c, &next, seen, out);
+            } else {
+                out.push_str(&format!("{}{}{} (already included)\n", indent, branch, c));
+            }
+        }
+    }
+}
+
+/// Names of the files included by `contents`, as written in the source; `\includeonly`
+/// only selects among them.
+pub fn include_names(contents: &str) -> Vec<String> {
+    let re = Regex::new(r"\\(?:input|include)\{(?P<content>[^}]+)\}").unwrap();
+    re.captures_iter(contents)
+        .map(|cap| cap["content"].to_owned())
+        .collect()
+}
+
+/// Identity of a file on disk, so that different spellings of the same path compare equal.
+pub fn file_id(fname: &str) -> PathBuf {
+    std::fs::canonicalize(fname).unwrap_or_else(|_| PathBuf::from(fname))
+}
+
+pub fn file_from_file<'a>(path: &'a str, fname: &'a str) -> Option<String> {
+    let mut wkdir = PathBuf::from(path);
+    let _ = wkdir.pop();
+    for ext in ["", ".tex", ".latex", ".bib", ".bbl"] {
+        let mut np = wkdir.clone();
+        let name = fname.to_owned() + ext;
+        np.push(name);
+        if np.is_file() {
+            return Some(np.to_str().unwrap().to_owned());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Creates the files of a document in a fresh directory, and a reader serving their contents.
+    fn document(name: &str, files: &[(&str, &str)]) -> (PathBuf, HashMap<String, String>) {
+        let dir =
+            std::env::temp_dir().join(format!("latex-thebib-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut contents = HashMap::new();
+        for (fname, text) in files {
+            let path = dir.join(fname);
+            std::fs::write(&path, "").unwrap();
+            contents.insert(path.to_str().unwrap().to_owned(), text.to_string());
+        }
+        (dir, contents)
+    }
+
+    fn build(dir: &Path, contents: &HashMap<String, String>) -> Result<IncludeGraph, String> {
+        let root = dir.join("main.tex");
+        IncludeGraph::build_with(root.to_str().unwrap(), |f| contents.get(f).cloned())
+    }
+
+    #[test]
+    fn reports_self_include() {
+        let (dir, contents) = document("self", &[("main.tex", "\\input{main}")]);
+        let main = dir.join("main.tex").to_str().unwrap().to_owned();
+        let err = build(&dir, &contents).err().unwrap();
+        assert_eq!(err, format!("include cycle: {} -> {}", main, main));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_indirect_cycle_with_its_chain() {
+        let (dir, contents) = document(
+            "cycle",
+            &[
+                ("main.tex", "\\input{a}"),
+                ("a.tex", "\\include{b}"),
+                ("b.tex", "\\input{a}"),
+            ],
+        );
+        let name = |f: &str| dir.join(f).to_str().unwrap().to_owned();
+        let err = build(&dir, &contents).err().unwrap();
+        assert_eq!(
+            err,
+            format!(
+                "include cycle: {} -> {} -> {}",
+                name("a.tex"),
+                name("b.tex"),
+                name("a.tex")
+            )
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lists_repeated_includes_once() {
+        let (dir, contents) = document(
+            "repeated",
+            &[
+                ("main.tex", "\\input{a}\n\\input{shared}\n\\input{a.tex}"),
+                ("a.tex", "\\input{shared}"),
+                ("shared.tex", "text"),
+            ],
+        );
+        let name = |f: &str| dir.join(f).to_str().unwrap().to_owned();
+        let graph = build(&dir, &contents).unwrap();
+        assert_eq!(
+            graph.files(),
+            [name("main.tex"), name("a.tex"), name("shared.tex")]
+        );
+        assert!(graph.tree().contains("(already included)"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+//! # journals
+//! Journal name abbreviation and expansion, from lists in the JabRef CSV
+//! format: one `Full Name;Abbreviation` per line, optionally quoted, further
+//! columns ignored.
+//!
+//! Names are matched ignoring case, punctuation, spacing and the spelling
+//! of accented letters, so `{Phys. Rev. Lett.}`, `Phys Rev Lett` and
+//! `Physical Review Letters` all find the same journal.
+use crate::unicode;
+use clap::ValueEnum;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum JournalMode {
+    /// keep journal names as written
+    Asis,
+    /// replace journal names with their abbreviation
+    Abbreviate,
+    /// replace abbreviated journal names with the full name
+    Expand,
+}
+
+pub struct JournalList {
+    /// Full name and abbreviation of each journal.
+    journals: Vec<(String, String)>,
+    /// Matching key of both names to the index of the journal.
+    index: HashMap<String, usize>,
+}
+
+/// Matching key of a journal name: lowercase words, without punctuation.
+fn key(name: &str) -> String {
+    unicode::normalize(name)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn unquote(cell: &str) -> &str {
+    let cell = cell.trim();
+    cell.strip_prefix('"')
+        .and_then(|c| c.strip_suffix('"'))
+        .unwrap_or(cell)
+        .trim()
+}
+
+impl JournalList {
+    pub fn read(fnames: &[String]) -> Result<JournalList, String> {
+        let mut list = JournalList {
+            journals: vec![],
+            index: HashMap::new(),
+        };
+        for fname in fnames {
+            let Ok(data) = std::fs::read_to_string(fname) else {
+                return Err(format!("Unable to read file \"{}\"", fname));
+            };
+            for (n, line) in data.lines().enumerate() {
+                let line = line.trim_start_matches('\u{feff}').trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut cells = line.split(';').map(unquote);
+                let (Some(full), Some(abbr)) = (cells.next(), cells.next()) else {
+                    return Err(format!(
+                        "{}: line {}: expected \"Full Name;Abbreviation\"",
+                        fname,
+                        n + 1
+                    ));
+                };
+                list.add(full, abbr);
+            }
+        }
+        Ok(list)
+    }
+
+    /// Earlier lists and lines win when a name appears twice.
+    fn add(&mut self, full: &str, abbr: &str) {
+        let i = self.journals.len();
+        self.journals.push((full.to_owned(), abbr.to_owned()));
+        for name in [full, abbr] {
+            self.index.entry(key(name)).or_insert(i);
+        }
+    }
+
+    /// Name of the journal in the requested form, `None` for journals not in the list.
+    pub fn convert<'a>(&'a self, name: &'a str, mode: JournalMode) -> Option<&'a str> {
+        let (full, abbr) = &self.journals[*self.index.get(&key(name))?];
+        match mode {
+            JournalMode::Asis => Some(name),
+            JournalMode::Abbreviate => Some(abbr),
+            JournalMode::Expand => Some(full),
+        }
+    }
+}
@@ -1,7 +1,30 @@
 use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+mod auxfile;
+mod bibtex;
+mod case;
 mod compile;
+mod crossref;
+mod csl;
+mod diagnostics;
+mod endnote;
+mod export;
+mod format;
+mod import;
+mod includes;
+mod journals;
+mod merge;
+mod names;
+mod pages;
 mod refactor;
+mod rekey;
+mod ris;
+mod thebibliography;
+mod unicode;
 mod utils;
+mod volume;
+mod watch;
+mod web;
 #[derive(Parser)]
 #[command(author, version, about)]
 #[command(propagate_version = true)]
@@ -16,16 +39,46 @@ enum Commands {
     Refactor(refactor::RefactorCli),
     /// Compile command, it is capable of turning a BibTeX file into a `thebibliography` environment.
     Compile(compile::CompileCli),
+    /// Deps command, it prints the tree of files included by a TeX document via `\input` and `\include`.
+    Deps(includes::DepsCli),
+    /// Rekey command, it renames citation keys across all the files of a TeX document, following a template.
+    Rekey(rekey::RekeyCli),
+    /// Format command, it rewrites a BibTeX file in a canonical, consistently indented and sorted form.
+    Format(format::FormatCli),
+    /// Merge command, it merges several BibTeX files, collapsing duplicated entries and resolving conflicts.
+    Merge(merge::MergeCli),
+    /// Bibtex command, it replaces the `bibtex` program: it reads the citations of a .aux file and writes the .bbl file with the compile formatter.
+    Bibtex(auxfile::BibtexCli),
 }
 
-fn main() {
+fn main() -> ExitCode {
     let cli = Cli::parse();
     match &cli.command {
         Commands::Compile(cli) => {
+            diagnostics::init(&cli.diagnostics);
             compile::run_compile(cli);
         }
         Commands::Refactor(cli) => {
+            diagnostics::init(&cli.diagnostics);
             refactor::run_refactor(cli);
         }
+        Commands::Deps(cli) => {
+            includes::run_deps(cli);
+        }
+        Commands::Rekey(cli) => {
+            rekey::run_rekey(cli);
+        }
+        Commands::Format(cli) => {
+            format::run_format(cli);
+        }
+        Commands::Merge(cli) => {
+            merge::run_merge(cli);
+        }
+        Commands::Bibtex(cli) => {
+            diagnostics::init(&cli.diagnostics);
+            auxfile::run_bibtex(cli);
+        }
     }
+    diagnostics::finish();
+    diagnostics::exit_code()
 }
@@ -0,0 +1,246 @@
+//! # merge
+//! Merges several BibTeX databases into one.
+//!
+//! Entries sharing a DOI, or a key and (nearly) the same title, are collapsed
+//! into one, and the fields they disagree on are reported and resolved
+//! according to `--policy`. Entries that only share a key are different
+//! papers, the later ones are renamed with an `a`, `b`, ... suffix.
+//!
+//! Run it as `latex-thebib merge a.bib b.bib -o merged.bib`.
+use crate::bibtex::{self, Block, Entry};
+use crate::compile::DEF_OUTPUT;
+use crate::diagnostics;
+use crate::format;
+use crate::import;
+use crate::pages;
+use crate::unicode;
+use crate::utils;
+use clap::{Args, ValueEnum};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Args)]
+pub struct MergeCli {
+    #[arg(required = true)]
+    /// BibTeX, RIS or EndNote XML files to merge, earlier files take precedence
+    files: Vec<String>,
+    #[arg(short, long, default_value = DEF_OUTPUT)]
+    /// Output BibTeX file name
+    output: String,
+    #[arg(short, long, value_enum, default_value_t = Policy::PreferFirst)]
+    /// How to resolve fields that differ between two copies of the same entry
+    policy: Policy,
+    #[arg(short, long, default_value_t = 0.3)]
+    /// Threshold for deciding that two entries with the same key are the same paper
+    threshold: f64,
+    #[arg(short, long, default_value_t = false)]
+    /// keep entries in their original order instead of sorting them by key
+    keep_order: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Policy {
+    /// keep the value found first
+    PreferFirst,
+    /// keep the values of the entry with more fields
+    PreferMostComplete,
+    /// stop without writing anything
+    Fail,
+}
+
+pub fn run_merge(cli: &MergeCli) {
+    let mut blocks = vec![];
+    for fname in cli.files.iter() {
+        match import::read_blocks(fname, false) {
+            Ok(mut b) => blocks.append(&mut b),
+            Err(e) => {
+                diagnostics::error(e);
+                return;
+            }
+        }
+    }
+    let mut merger = Merger {
+        entries: vec![],
+        strings: bibtex::string_table(&blocks),
+        policy: cli.policy,
+        th: cli.threshold,
+    };
+    match merger.merge_blocks(blocks) {
+        Ok(merged) => {
+            let formatted = format::format_blocks(merged, !cli.keep_order);
+            if cli.output != DEF_OUTPUT {
+                utils::write_file(cli.output.to_owned(), &formatted);
+            } else {
+                print!("{}", formatted);
+            }
+        }
+        Err(e) => diagnostics::error(e),
+    }
+}
+
+struct Merger {
+    entries: Vec<Entry>,
+    strings: HashMap<String, String>,
+    policy: Policy,
+    th: f64,
+}
+
+impl Merger {
+    fn merge_blocks(&mut self, blocks: Vec<Block>) -> Result<Vec<Block>, String> {
+        let mut merged: Vec<Block> = vec![];
+        for b in blocks {
+            match b {
+                Block::Entry(e) => self.merge_entry(e)?,
+                Block::String(s) => {
+                    let same_name = merged.iter().find_map(|m| match m {
+                        Block::String(t) if t.name.eq_ignore_ascii_case(&s.name) => Some(t),
+                        _ => None,
+                    });
+                    match same_name {
+                        Some(t) if t.value != s.value => diagnostics::warning(format!(
+                            "@string \"{}\" is defined as {} and as {}, keeping the first",
+                            s.name, t.value, s.value
+                        )),
+                        Some(_) => {}
+                        None => merged.push(Block::String(s)),
+                    }
+                }
+                b => {
+                    if !merged.contains(&b) {
+                        merged.push(b);
+                    }
+                }
+            }
+        }
+        merged.extend(self.entries.drain(..).map(Block::Entry));
+        Ok(merged)
+    }
+
+    fn merge_entry(&mut self, e: Entry) -> Result<(), String> {
+        let doi = self.doi(&e);
+        if let Some(i) = self
+            .entries
+            .iter()
+            .position(|o| doi.is_some() && self.doi(o) == doi)
+        {
+            if !self.entries[i].key.eq_ignore_ascii_case(&e.key) {
+                diagnostics::warning(format!(
+                    "\"{}\" has the same DOI as \"{}\", merged into it",
+                    e.key, self.entries[i].key
+                ));
+            }
+            return self.combine(i, e);
+        }
+        let Some(i) = self
+            .entries
+            .iter()
+            .position(|o| o.key.eq_ignore_ascii_case(&e.key))
+        else {
+            self.entries.push(e);
+            return Ok(());
+        };
+        if self.same_paper(&self.entries[i], &e) {
+            return self.combine(i, e);
+        }
+        let mut e = e;
+        let taken = self
+            .entries
+            .iter()
+            .map(|o| o.key.to_lowercase())
+            .collect::<HashSet<String>>();
+        let key = (0..)
+            .map(|n| format!("{}{}", e.key, utils::letter_suffix(n)))
+            .find(|k| !taken.contains(&k.to_lowercase()))
+            .unwrap();
+        diagnostics::warning(format!(
+            "key \"{}\" is used by two different entries, the second one is renamed \"{}\"",
+            e.key, key
+        ));
+        e.key = key;
+        self.entries.push(e);
+        Ok(())
+    }
+
+    /// Merges `other` into `entry`, reporting the fields they disagree on.
+    fn combine(&mut self, i: usize, other: Entry) -> Result<(), String> {
+        let conflicts = other
+            .fields
+            .iter()
+            .filter(|f| {
+                if f.name.eq_ignore_ascii_case("doi") {
+                    return self
+                        .doi(&self.entries[i])
+                        .is_some_and(|d| Some(d) != self.doi(&other));
+                }
+                self.entries[i].field(&f.name).is_some_and(|g| {
+                    let (a, b) = (self.normalize(&g.value), self.normalize(&f.value));
+                    if f.name.eq_ignore_ascii_case("pages") {
+                        pages::canonical(&a) != pages::canonical(&b)
+                    } else {
+                        a != b
+                    }
+                })
+            })
+            .map(|f| f.name.to_lowercase())
+            .collect::<Vec<String>>();
+        for name in conflicts.iter() {
+            let msg = format!(
+                "entry \"{}\" has conflicting \"{}\": {} vs {}",
+                self.entries[i].key,
+                name,
+                self.entries[i].field(name).unwrap().value,
+                other.field(name).unwrap().value
+            );
+            if self.policy == Policy::Fail {
+                return Err(msg);
+            }
+            diagnostics::warning(msg);
+        }
+        let entry = &mut self.entries[i];
+        let other_first =
+            self.policy == Policy::PreferMostComplete && other.fields.len() > entry.fields.len();
+        for f in other.fields.into_iter() {
+            match entry
+                .fields
+                .iter_mut()
+                .find(|g| g.name.eq_ignore_ascii_case(&f.name))
+            {
+                Some(g) if other_first => g.value = f.value,
+                Some(_) => {}
+                None => entry.fields.push(f),
+            }
+        }
+        if other_first {
+            entry.kind = other.kind;
+        }
+        Ok(())
+    }
+
+    fn same_paper(&self, a: &Entry, b: &Entry) -> bool {
+        match (a.get("title"), b.get("title")) {
+            (Some(ta), Some(tb)) => {
+                utils::text_distance(&self.normalize(ta), &self.normalize(tb)) <= self.th
+            }
+            _ => {
+                a.get("author").map(|v| self.normalize(v))
+                    == b.get("author").map(|v| self.normalize(v))
+                    && a.get("year") == b.get("year")
+            }
+        }
+    }
+
+    fn doi(&self, e: &Entry) -> Option<String> {
+        let d = self.normalize(&e.field("doi")?.value);
+        let d = d
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("dx.")
+            .trim_start_matches("doi.org/")
+            .trim_start_matches("doi:");
+        Some(d.to_owned())
+    }
+
+    /// Comparable form of a value: macros expanded, accents as Unicode, lowercase, single spaces.
+    fn normalize(&self, value: &str) -> String {
+        unicode::normalize(&bibtex::expand(value, &self.strings))
+    }
+}
@@ -0,0 +1,261 @@
+//! # names
+//! BibTeX name lists: `A and B and C`, each name written as
+//! `First von Last`, `von Last, First` or `von Last, Jr, First`.
+//!
+//! Splitting follows the rules of BibTeX itself: only top-level separators
+//! count, so `{Barnes and Noble}` is a single name, and a name entirely
+//! wrapped in braces is a literal (e.g. a corporate author).
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Name {
+    pub first: String,
+    pub von: String,
+    pub last: String,
+    pub jr: String,
+    /// The whole name was braced, it must not be split or abbreviated.
+    pub literal: bool,
+}
+
+/// Splits a name list on the top-level, case-insensitive `and`.
+pub fn split_names(list: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut level = 0;
+    let mut start = 0;
+    let bytes = list.as_bytes();
+    for (i, c) in list.char_indices() {
+        match c {
+            '{' => level += 1,
+            '}' => level -= 1,
+            c if level == 0
+                && c.is_ascii_whitespace()
+                && bytes.len() > i + 4
+                && bytes[i + 1..i + 4].eq_ignore_ascii_case(b"and")
+                && bytes[i + 4].is_ascii_whitespace() =>
+            {
+                names.push(list[start..i].trim());
+                start = i + 4;
+            }
+            _ => {}
+        }
+    }
+    names.push(list[start..].trim());
+    names.retain(|n| !n.is_empty());
+    names
+}
+
+pub fn parse_names(list: &str) -> Vec<Name> {
+    split_names(list).into_iter().map(parse_name).collect()
+}
+
+pub fn parse_name(name: &str) -> Name {
+    let name = name.trim();
+    if let Some(inner) = name.strip_prefix('{').and_then(|n| n.strip_suffix('}')) {
+        if words(inner).len() > 1 && balanced(inner) {
+            return Name {
+                last: inner.to_owned(),
+                literal: true,
+                ..Default::default()
+            };
+        }
+    }
+    let parts = split_top_level(name, ',');
+    match parts.len() {
+        1 => {
+            let w = words(parts[0]);
+            // von starts at the first lowercase word, the last word is always in Last
+            let n = w.len();
+            let von_start = w[..n.saturating_sub(1)].iter().position(|x| is_lower(x));
+            match von_start {
+                Some(s) => {
+                    let von_end = (s..n - 1).rev().find(|&i| is_lower(w[i])).unwrap() + 1;
+                    Name {
+                        first: w[..s].join(" "),
+                        von: w[s..von_end].join(" "),
+                        last: w[von_end..].join(" "),
+                        ..Default::default()
+                    }
+                }
+                None => Name {
+                    first: w[..n.saturating_sub(1)].join(" "),
+                    last: w.last().map(|l| l.to_string()).unwrap_or_default(),
+                    ..Default::default()
+                },
+            }
+        }
+        n => {
+            let (von, last) = split_von_last(parts[0]);
+            Name {
+                first: words(parts[n - 1]).join(" "),
+                von,
+                last,
+                jr: if n > 2 {
+                    words(parts[1]).join(" ")
+                } else {
+                    "".to_owned()
+                },
+                literal: false,
+            }
+        }
+    }
+}
+
+/// `von Last` part of the comma forms, von is everything up to the last lowercase word.
+fn split_von_last(s: &str) -> (String, String) {
+    let w = words(s);
+    let n = w.len();
+    match (0..n.saturating_sub(1)).rev().find(|&i| is_lower(w[i])) {
+        Some(e) => (w[..=e].join(" "), w[e + 1..].join(" ")),
+        None => ("".to_owned(), w.join(" ")),
+    }
+}
+
+fn balanced(s: &str) -> bool {
+    let mut level = 0;
+    for c in s.chars() {
+        match c {
+            '{' => level += 1,
+            '}' if level == 0 => return false,
+            '}' => level -= 1,
+            _ => {}
+        }
+    }
+    level == 0
+}
+
+fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut level = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => level += 1,
+            '}' => level -= 1,
+            c if c == sep && level == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts
+}
+
+/// Words separated by top-level whitespace or `~`.
+fn words(s: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut level = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => level += 1,
+            '}' => level -= 1,
+            c if level == 0 && (c.is_whitespace() || c == '~') => {
+                if i > start {
+                    words.push(&s[start..i]);
+                }
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    if start < s.len() {
+        words.push(&s[start..]);
+    }
+    words
+}
+
+/// Case of a word is the case of its first top-level letter; `{\'e}...` counts as a letter,
+/// other braced groups make the word caseless.
+fn is_lower(word: &str) -> bool {
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '{' {
+            if chars.peek() != Some(&'\\') {
+                return false;
+            }
+            chars.next();
+            // skip the command name, then look at the letter it applies to
+            let cmd = chars
+                .by_ref()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect::<String>();
+            if cmd.len() > 1 {
+                return cmd.chars().next().unwrap().is_lowercase();
+            }
+            return chars
+                .find(|c| c.is_alphabetic())
+                .is_some_and(|c| c.is_lowercase());
+        }
+        if c.is_alphabetic() {
+            return c.is_lowercase();
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(first: &str, von: &str, last: &str, jr: &str) -> Name {
+        Name {
+            first: first.to_owned(),
+            von: von.to_owned(),
+            last: last.to_owned(),
+            jr: jr.to_owned(),
+            literal: false,
+        }
+    }
+
+    #[test]
+    fn splits_on_top_level_and() {
+        assert_eq!(
+            split_names("Smith, John and Jane Doe AND {Barnes and Noble}"),
+            vec!["Smith, John", "Jane Doe", "{Barnes and Noble}"]
+        );
+        assert_eq!(
+            split_names("Alexander Anderson"),
+            vec!["Alexander Anderson"]
+        );
+        assert!(split_names("  ").is_empty());
+    }
+
+    #[test]
+    fn parses_the_three_forms() {
+        assert_eq!(
+            parse_name("Donald E. Knuth"),
+            name("Donald E.", "", "Knuth", "")
+        );
+        assert_eq!(
+            parse_name("Knuth, Donald E."),
+            name("Donald E.", "", "Knuth", "")
+        );
+        assert_eq!(
+            parse_name("Ludwig van Beethoven"),
+            name("Ludwig", "van", "Beethoven", "")
+        );
+        assert_eq!(
+            parse_name("van Beethoven, Ludwig"),
+            name("Ludwig", "van", "Beethoven", "")
+        );
+        assert_eq!(
+            parse_name("Martin Luther King"),
+            name("Martin Luther", "", "King", "")
+        );
+        assert_eq!(
+            parse_name("King, Jr, Martin Luther"),
+            name("Martin Luther", "", "King", "Jr")
+        );
+    }
+
+    #[test]
+    fn keeps_braced_names_whole() {
+        let n = parse_name("{World Health Organization}");
+        assert!(n.literal);
+        assert_eq!(n.last, "World Health Organization");
+        assert_eq!(parse_name("{van Gogh}, Vincent").last, "{van Gogh}");
+        assert_eq!(parse_names("Plato").len(), 1);
+        assert_eq!(parse_names("Plato")[0].last, "Plato");
+    }
+}
@@ -0,0 +1,185 @@
+//! # pages
+//! Page ranges: `123-130`, `123--130`, `123 – 130` and `pp. 123{\textendash}130`
+//! are the same range, first page 123 and last page 130. Single values with a
+//! letter prefix (`e1234`) or written as `Article 1234` are article numbers.
+use crate::unicode;
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum PageStyle {
+    /// first and last page, `123--130`
+    Full,
+    /// first page only, `123`
+    First,
+    /// last page without the leading digits it shares with the first, `123--30`
+    Abbreviated,
+}
+
+const PREFIXES: [&str; 9] = [
+    "article number",
+    "article no.",
+    "article",
+    "art. no.",
+    "art.",
+    "pages",
+    "pp.",
+    "p.",
+    "no.",
+];
+
+pub struct Pages {
+    pub first: String,
+    pub last: Option<String>,
+    pub article: bool,
+}
+
+impl Pages {
+    /// `None` for empty values and values that are not a single page or a range.
+    pub fn parse(s: &str) -> Option<Pages> {
+        let text = unicode::to_unicode(s);
+        let mut rest = text.trim();
+        let mut article = false;
+        for p in PREFIXES {
+            if rest.len() >= p.len()
+                && rest.is_char_boundary(p.len())
+                && rest[..p.len()].eq_ignore_ascii_case(p)
+            {
+                article = p.starts_with("art");
+                rest = rest[p.len()..].trim_start();
+                break;
+            }
+        }
+        let parts = rest
+            .split(['-', '–', '—', '−'])
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<&str>>();
+        let valid = |p: &&str| p.chars().all(|c| c.is_alphanumeric() || c == '.');
+        if parts.is_empty() || parts.len() > 2 || !parts.iter().all(valid) {
+            return None;
+        }
+        let first = parts[0].to_owned();
+        let last = parts.get(1).map(|l| l.to_string()).filter(|l| *l != first);
+        if last.is_none()
+            && first.starts_with(char::is_alphabetic)
+            && first.ends_with(|c: char| c.is_ascii_digit())
+        {
+            article = true;
+        }
+        Some(Pages {
+            first,
+            last,
+            article,
+        })
+    }
+
+    pub fn format(&self, style: PageStyle) -> String {
+        let last = match &self.last {
+            Some(l) if !self.article && style != PageStyle::First => l,
+            _ => return self.first.to_owned(),
+        };
+        if style == PageStyle::Abbreviated {
+            return format!("{}--{}", self.first, abbreviate(&self.first, last));
+        }
+        format!("{}--{}", self.first, last)
+    }
+
+    /// Numeric value of the first page, for sorting.
+    pub fn number(&self) -> Option<u64> {
+        let digits = self.first.trim_start_matches(|c: char| !c.is_ascii_digit());
+        digits.parse().ok()
+    }
+}
+
+/// Last page of a range without the leading digits shared with the first, keeping at
+/// least two digits: 123--130 becomes 123--30, 1998--2004 is kept.
+fn abbreviate<'a>(first: &str, last: &'a str) -> &'a str {
+    let numeric = |s: &str| !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit());
+    if !numeric(first) || !numeric(last) || first.len() != last.len() || last <= first {
+        return last;
+    }
+    let common = first
+        .bytes()
+        .zip(last.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let keep = (last.len() - common).max(2).min(last.len());
+    &last[last.len() - keep..]
+}
+
+/// Comparable form of a pages value: the full range, or the value itself when it is not one.
+pub fn canonical(s: &str) -> String {
+    match Pages::parse(s) {
+        Some(p) => unicode::normalize(&p.format(PageStyle::Full)),
+        None => unicode::normalize(s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(s: &str, style: PageStyle) -> Option<String> {
+        Pages::parse(s).map(|p| p.format(style))
+    }
+
+    #[test]
+    fn parses_ranges() {
+        for s in [
+            "123-130",
+            "123--130",
+            "123 – 130",
+            "pp. 123--130",
+            "123--130 ",
+        ] {
+            assert_eq!(
+                format(s, PageStyle::Full).as_deref(),
+                Some("123--130"),
+                "{}",
+                s
+            );
+        }
+        assert_eq!(format("p. 42", PageStyle::Full).as_deref(), Some("42"));
+        assert_eq!(format("42--42", PageStyle::Full).as_deref(), Some("42"));
+        assert_eq!(format("S1--S9", PageStyle::Full).as_deref(), Some("S1--S9"));
+    }
+
+    #[test]
+    fn rejects_other_values() {
+        assert!(Pages::parse("").is_none());
+        assert!(Pages::parse("1-2-3").is_none());
+        assert!(Pages::parse("123, 130").is_none());
+    }
+
+    #[test]
+    fn recognises_article_numbers() {
+        let p = Pages::parse("e1002345").unwrap();
+        assert!(p.article);
+        let p = Pages::parse("art. no. 045001").unwrap();
+        assert!(p.article);
+        assert_eq!(p.format(PageStyle::Full), "045001");
+        assert_eq!(p.number(), Some(45001));
+    }
+
+    #[test]
+    fn formats_styles() {
+        assert_eq!(format("123--130", PageStyle::First).as_deref(), Some("123"));
+        assert_eq!(
+            format("123--130", PageStyle::Abbreviated).as_deref(),
+            Some("123--30")
+        );
+        assert_eq!(
+            format("123--198", PageStyle::Abbreviated).as_deref(),
+            Some("123--98")
+        );
+        assert_eq!(
+            format("1998--2004", PageStyle::Abbreviated).as_deref(),
+            Some("1998--2004")
+        );
+        assert_eq!(
+            format("9--12", PageStyle::Abbreviated).as_deref(),
+            Some("9--12")
+        );
+        assert_eq!(canonical("pp. 5-7"), canonical("5--7"));
+    }
+}
@@ -5,13 +5,20 @@
 //! Run it as `latex-thebib -h` for help.
 //!
 //! Run it as `latex-thebib -f master.tex` for basic functionality.
+use crate::compile::{self, CompileOptions};
+use crate::diagnostics::{self, DiagnosticsCli};
+use crate::export::{self, Export};
+use crate::includes::{self, IncludeGraph};
+use crate::thebibliography::{self, BibEntry, Thebibliography};
 use crate::utils;
-use clap::Args;
+use crate::watch;
+use clap::{Args, ValueEnum};
 use regex::Regex;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::hash::Hash;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 #[derive(Args)]
 pub struct RefactorCli {
@@ -24,13 +31,56 @@ pub struct RefactorCli {
     #[arg(short, long, default_value = "cleaned")]
     /// Subdir of master-file to put all the outputs
     subdir: String,
+    #[arg(long, default_value_t = false)]
+    /// Treat every `thebibliography` environment as a separate bibliography, serving the citations between it and the previous one
+    scoped: bool,
+    #[arg(short, long, value_enum, default_value_t = Order::Citation)]
+    /// Order of the refactored bibliography
+    order: Order,
+    #[arg(short, long, value_enum)]
+    /// Also write the refactored entries, with their fields and replaced keys, next to the outputs
+    export: Option<Export>,
+    #[arg(long, default_value_t = false)]
+    /// Replace `\bibliography{...}` with a `thebibliography` environment compiled from the cited entries of those .bib files
+    inline_bib: bool,
+    #[arg(long, default_value_t = false)]
+    /// Write the whole document as a single file, with included files inlined and one bibliography
+    flatten: bool,
+    #[arg(short, long, default_value_t = false)]
+    /// refactor again whenever a file of the document changes, until interrupted
+    watch: bool,
+    #[command(flatten)]
+    pub diagnostics: DiagnosticsCli,
 }
 
-#[derive(Debug)]
-struct Cite {
-    list: Vec<String>,
-    kind: String,
-    raw: String,
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Order {
+    /// order of first citation
+    Citation,
+    /// alphabetical by key
+    Key,
+    /// alphabetical by first author, then year
+    Author,
+    /// by year, then first author
+    Year,
+    /// as found in the source bibliography
+    Source,
+}
+
+/// Citation commands of LaTeX, natbib and biblatex, with their star and optional arguments in
+/// `cmd` and the list of keys in `keys`.
+pub const CITE_COMMAND: &str = concat!(
+    r"(?P<cmd>\\(?:(?:[cC]ite(?:t|p|alt|alp|author|year|yearpar|num)?|nocite|[pP]arencite",
+    r"|[tT]extcite|[aA]utocite|[fF]ootcite|fullcite|[sS]upercite|[sS]martcite)\*?(?:\s*\[[^\]]*\])*)\s*)",
+    r"\{(?P<keys>[^}]*)\}",
+);
+
+#[derive(Debug, Clone)]
+pub struct Cite {
+    pub list: Vec<String>,
+    /// The command as written, without the backslash, e.g. `citep[p.~3]`.
+    pub kind: String,
+    pub scope: usize,
 }
 
 impl fmt::Display for Cite {
@@ -39,160 +89,606 @@ impl fmt::Display for Cite {
     }
 }
 
-#[derive(Debug, Hash, Clone)]
-struct BibEntry {
-    key: String,
+/// What a span of a source file holds, indices point into the `Document` lists.
+#[derive(Clone)]
+enum Token {
+    Cite(usize),
+    Bibliography(usize),
+    /// `\input` or, when `clearpage`, `\include` of `file`, written `name` in the source.
+    Include {
+        clearpage: bool,
+        name: String,
+        file: String,
+    },
+    /// The names of an `\includeonly` list.
+    IncludeOnly(Vec<String>),
+}
+
+pub struct Source {
+    pub fname: String,
+    contents: String,
+    tokens: Vec<(Range<usize>, Token)>,
+}
+
+/// The whole document, with citations and `thebibliography` environments in reading order.
+pub struct Document {
+    pub sources: Vec<Source>,
+    pub cites: Vec<Cite>,
+    pub bibliographies: Vec<Thebibliography>,
+}
+
+/// A source file parsed on its own: the indices of its tokens point into its own lists.
+#[derive(Clone)]
+struct Parsed {
+    contents: String,
+    tokens: Vec<(Range<usize>, Token)>,
+    cites: Vec<Cite>,
+    bibliographies: Vec<Thebibliography>,
+}
+
+struct Cached {
+    stamp: watch::Stamp,
+    /// Contents with comments stripped.
     text: String,
+    /// Parses of `text`, by the bibliography that replaced its `\bibliography{...}`: the
+    /// one without and the latest one with.
+    parsed: HashMap<Option<String>, Parsed>,
 }
 
-impl fmt::Display for BibEntry {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "\\bibitem{{{}}} {}", self.key, self.text)
+/// Sources read by the previous runs, so that watch mode only reads and parses the changed files.
+#[derive(Default)]
+struct Cache {
+    sources: HashMap<String, Cached>,
+    /// Blocks of the .bib files read by `--inline-bib`.
+    databases: compile::Cache,
+    /// Files of the document and .bib files of the last run.
+    watched: Vec<String>,
+}
+
+impl Cache {
+    fn text(&mut self, fname: &str) -> Option<String> {
+        let stamp = watch::stamp(fname);
+        if let Some(c) = self.sources.get(fname) {
+            if stamp.is_some() && c.stamp == stamp {
+                return Some(c.text.to_owned());
+            }
+        }
+        let text = utils::read_tex_stripped(fname)?;
+        self.sources.insert(
+            fname.to_owned(),
+            Cached {
+                stamp,
+                text: text.to_owned(),
+                parsed: HashMap::new(),
+            },
+        );
+        Some(text)
+    }
+
+    fn parsed(&mut self, fname: &str, bbl: Option<&str>) -> Parsed {
+        let text = self.text(fname).expect("File cannot be read.");
+        // the bibliography only makes a difference to files with a \bibliography command
+        let bbl = bbl
+            .filter(|_| text.contains("\\bibliography"))
+            .map(|b| b.to_owned());
+        let cached = self.sources.get_mut(fname).unwrap();
+        if let Some(parsed) = cached.parsed.get(&bbl) {
+            return parsed.clone();
+        }
+        let parsed = parse_source(fname, text, bbl.as_deref());
+        if bbl.is_some() {
+            cached.parsed.retain(|b, _| b.is_none());
+        }
+        cached.parsed.insert(bbl, parsed.clone());
+        parsed
     }
 }
 
 pub fn run_refactor(cli: &RefactorCli) {
-    let (cites, bib) = parse_citations_and_biblio(&cli.file);
-    let (clean_cites, used_bib) = take_used(&bib, &cites, cli.threshold);
-    apply_changes(&cli.file, &used_bib, &clean_cites, &cli);
+    if cli.watch {
+        let cache = RefCell::new(Cache::default());
+        let files = || cache.borrow().watched.clone();
+        watch::watch(files, || refactor(cli, &mut cache.borrow_mut()));
+    }
+    refactor(cli, &mut Cache::default());
 }
 
-fn parse_citations_and_biblio(fname: &str) -> (Vec<Cite>, Vec<BibEntry>) {
-    let contents = utils::read_tex_stripped(fname).expect("File cannot be read.");
-    let mut cite_list: Vec<Cite> = vec![];
-    let mut bib_list: Vec<BibEntry> = vec![];
-    bib_list.append(&mut parse_bibliography(&contents));
-    let re_cite_input = Regex::new(r"\\cite\{[^}]+\}|\\citet\{[^}]+\}|\\citep\{[^}]+\}|\\input\{[^}]+\}|\\include\{[^}]+\}|\\includeonly\{[^}]+\}").unwrap();
-    let re_parse_cmd = Regex::new(r"\\(?P<type>[a-zA-Z]*)\{(?P<content>.*)\}").unwrap();
-    for cap in re_cite_input.captures_iter(&contents) {
-        let tok = &cap[0];
-        let match_tok = re_parse_cmd.captures(&tok).unwrap();
-        let t = &match_tok["type"];
-        let c = &match_tok["content"];
-        if t == "cite" || t == "citet" || t == "citep" {
-            let mut entry = Cite {
-                list: vec![],
-                kind: t.trim().to_owned(),
-                raw: match_tok[0].to_owned(),
-            };
-            for cite in c.split(',') {
-                let cite = cite.trim();
-                entry.list.push(cite.to_owned());
+fn refactor(cli: &RefactorCli, cache: &mut Cache) {
+    let mut read = vec![cli.file.to_owned()];
+    let graph = IncludeGraph::build_with(&cli.file, |f| {
+        read.push(f.to_owned());
+        cache.text(f)
+    });
+    match graph {
+        Ok(graph) => cache.watched = graph.files().to_vec(),
+        Err(e) => {
+            // keep watching the files of the last run too, the error may be fixed in any of them
+            for f in read {
+                if !cache.watched.contains(&f) {
+                    cache.watched.push(f);
+                }
             }
-            cite_list.push(entry);
+            diagnostics::error(e);
+            return;
+        }
+    }
+    let mut doc = read_document(&cli.file, None, cache);
+    if cli.inline_bib {
+        match inline_bibliography(&doc, &cli.file, cache) {
+            Ok(Some(bbl)) => doc = read_document(&cli.file, Some(&bbl), cache),
+            Ok(None) => diagnostics::warning("no \\bibliography command to inline"),
+            Err(e) => {
+                diagnostics::error(e);
+                return;
+            }
+        }
+    }
+    let (clean_cites, mut used_bibs, replacements) = if cli.scoped {
+        take_used_scoped(&doc, cli.threshold)
+    } else {
+        let bib = doc
+            .bibliographies
+            .iter()
+            .flat_map(|b| b.items.iter().cloned())
+            .collect::<Vec<BibEntry>>();
+        let cites = doc.cites.iter().collect::<Vec<&Cite>>();
+        let (clean_cites, used_bib, replacements) = take_used(&bib, &cites, cli.threshold);
+        let used_bibs = vec![used_bib; doc.bibliographies.len()];
+        (clean_cites, used_bibs, replacements)
+    };
+    let mut replaced = replacements.iter().collect::<Vec<(&String, &String)>>();
+    replaced.sort();
+    for (old, new) in replaced {
+        diagnostics::info(format!(
+            "\"{}\" is a duplicate of \"{}\", citations replaced",
+            old, new
+        ));
+    }
+    if cli.order != Order::Citation {
+        let mut position = HashMap::new();
+        for (i, b) in doc
+            .bibliographies
+            .iter()
+            .flat_map(|b| b.items.iter())
+            .enumerate()
+        {
+            position.entry(b.key.to_owned()).or_insert(i);
+        }
+        for bib in used_bibs.iter_mut() {
+            sort_bib(bib, cli.order, &position);
+        }
+    }
+    if cli.flatten {
+        write_flattened(&doc, &used_bibs, &clean_cites, cli);
+    } else {
+        for src in doc.sources.iter() {
+            apply_changes(&doc, src, &used_bibs, &clean_cites, cli);
+        }
+    }
+    if let Some(format) = cli.export {
+        let known = doc
+            .bibliographies
+            .iter()
+            .flat_map(|b| b.items.iter().map(|b| b.key.as_str()))
+            .collect::<HashSet<&str>>();
+        let mut seen = HashSet::new();
+        let items = used_bibs
+            .iter()
+            .flatten()
+            .filter(|b| known.contains(b.key.as_str()) && seen.insert(b.key.as_str()))
+            .cloned()
+            .collect::<Vec<BibEntry>>();
+        let mut replacements = replacements.into_iter().collect::<Vec<(String, String)>>();
+        replacements.sort();
+        let fname = PathBuf::from(change_path(&cli.file, &cli.subdir).unwrap())
+            .with_extension(format.extension());
+        utils::write_file(
+            fname.to_str().unwrap().to_owned(),
+            &export::export(&items, &replacements, format),
+        );
+    }
+}
+
+/// Reads the document rooted at `fname`, its include graph must have been checked already.
+pub fn parse_document(fname: &str) -> Document {
+    read_document(fname, None, &mut Cache::default())
+}
+
+/// Reads the document, replacing `\bibliography{...}` with `bbl` and dropping `\bibliographystyle{...}`
+/// when given. Unchanged files are taken from `cache`.
+fn read_document(fname: &str, bbl: Option<&str>, cache: &mut Cache) -> Document {
+    let mut doc = Document {
+        sources: vec![],
+        cites: vec![],
+        bibliographies: vec![],
+    };
+    parse_citations_and_biblio(fname, &mut doc, &mut HashSet::new(), bbl, cache);
+    doc
+}
+
+/// `thebibliography` environment of the entries cited in the document, compiled from the
+/// .bib files of its `\bibliography` commands, relative to the master file; `None` without one.
+/// The .bib files are added to the watched files of `cache`.
+fn inline_bibliography(
+    doc: &Document,
+    master: &str,
+    cache: &mut Cache,
+) -> Result<Option<String>, String> {
+    let re = Regex::new(r"\\bibliography\{([^}]*)\}").unwrap();
+    let databases = doc
+        .sources
+        .iter()
+        .flat_map(|s| re.captures_iter(&s.contents).map(|c| c[1].to_owned()))
+        .flat_map(|list| {
+            list.split(',')
+                .map(|s| s.trim().to_owned())
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<String>>()
+        })
+        .collect::<Vec<String>>();
+    if databases.is_empty() {
+        return Ok(None);
+    }
+    let dir = Path::new(master).parent().unwrap_or(Path::new(""));
+    let mut blocks = vec![];
+    for db in databases {
+        let fname = if Path::new(&db).extension().is_some() {
+            dir.join(&db)
         } else {
-            let name =
-                file_from_file(fname, c).expect(&("Should have found file: ".to_owned() + c));
-            let (mut parsed, mut parsed_bib) = parse_citations_and_biblio(&name);
-            cite_list.append(&mut parsed);
-            bib_list.append(&mut parsed_bib);
+            dir.join(format!("{}.bib", db))
+        };
+        let fname = fname.to_str().unwrap();
+        cache.watched.push(fname.to_owned());
+        blocks.append(&mut compile::read_cached(fname, &mut cache.databases)?);
+    }
+    let mut keys: Vec<String> = vec![];
+    for k in doc.cites.iter().flat_map(|c| c.list.iter()) {
+        if !keys.contains(k) {
+            keys.push(k.to_owned());
         }
     }
-    return (cite_list, bib_list);
+    // \nocite{*} cites every entry
+    if keys.iter().any(|k| k == "*") {
+        keys.clear();
+    }
+    let options = CompileOptions::default();
+    let Some(bib) = compile::prepare(&blocks, &keys, &options) else {
+        return Err("Unable to compile the bibliography".to_owned());
+    };
+    Ok(Some(compile::format_thebibliography(bib, &options)))
 }
 
-fn take_used(raw_bib: &Vec<BibEntry>, cites: &Vec<Cite>, th: f64) -> (Vec<Cite>, Vec<BibEntry>) {
-    let (replacements, bib) = reduce_bib(&raw_bib, th);
+/// Adds the file and, at the place they are included, the files it includes to the document.
+fn parse_citations_and_biblio(
+    fname: &str,
+    doc: &mut Document,
+    seen: &mut HashSet<PathBuf>,
+    bbl: Option<&str>,
+    cache: &mut Cache,
+) {
+    seen.insert(includes::file_id(fname));
+    let parsed = cache.parsed(fname, bbl);
+    let mut tokens = vec![];
+    for (range, tok) in parsed.tokens {
+        let tok = match tok {
+            Token::Cite(i) => {
+                doc.cites.push(Cite {
+                    scope: doc.bibliographies.len(),
+                    ..parsed.cites[i].clone()
+                });
+                Token::Cite(doc.cites.len() - 1)
+            }
+            Token::Bibliography(i) => {
+                doc.bibliographies.push(parsed.bibliographies[i].clone());
+                Token::Bibliography(doc.bibliographies.len() - 1)
+            }
+            Token::Include { ref file, .. } => {
+                if !seen.contains(&includes::file_id(file)) {
+                    parse_citations_and_biblio(file, doc, seen, bbl, cache);
+                }
+                tok
+            }
+            Token::IncludeOnly(_) => tok,
+        };
+        tokens.push((range, tok));
+    }
+    doc.sources.push(Source {
+        fname: fname.to_owned(),
+        contents: parsed.contents,
+        tokens,
+    });
+}
+
+/// Parses the stripped contents of a file, replacing `\bibliography{...}` with `bbl` when given.
+fn parse_source(fname: &str, mut contents: String, bbl: Option<&str>) -> Parsed {
+    if let Some(bbl) = bbl {
+        let style = Regex::new(r"\\bibliographystyle\{[^}]*\}\n?").unwrap();
+        let bibliography = Regex::new(r"\\bibliography\{[^}]*\}").unwrap();
+        contents = style.replace_all(&contents, "").into_owned();
+        contents = bibliography
+            .replace_all(&contents, regex::NoExpand(bbl))
+            .into_owned();
+    }
+    let re_token = Regex::new(&format!(
+        r"(?s)\\begin\{{thebibliography\}}.*?\\end\{{thebibliography\}}|{}|\\(?P<type>input|include|includeonly)\{{(?P<content>[^}}]+)\}}",
+        CITE_COMMAND
+    ))
+    .unwrap();
+    let mut parsed = Parsed {
+        contents: String::new(),
+        tokens: vec![],
+        cites: vec![],
+        bibliographies: vec![],
+    };
+    for cap in re_token.captures_iter(&contents) {
+        let range = cap.get(0).unwrap().range();
+        if let Some(cmd) = cap.name("cmd") {
+            parsed.tokens.push((range, Token::Cite(parsed.cites.len())));
+            parsed.cites.push(Cite {
+                list: cap["keys"]
+                    .split(',')
+                    .map(|cite| cite.trim().to_owned())
+                    .filter(|cite| !cite.is_empty())
+                    .collect(),
+                kind: cmd.as_str()[1..].to_owned(),
+                scope: 0,
+            });
+            continue;
+        }
+        let Some(t) = cap.name("type").map(|m| m.as_str()) else {
+            let i = parsed.bibliographies.len();
+            parsed.tokens.push((range, Token::Bibliography(i)));
+            parsed.bibliographies.push(Thebibliography::parse(&cap[0]));
+            continue;
+        };
+        let c = &cap["content"];
+        if t == "includeonly" {
+            let names = c.split(',').map(|n| n.trim().to_owned()).collect();
+            parsed.tokens.push((range, Token::IncludeOnly(names)));
+        } else {
+            let name = includes::file_from_file(fname, c)
+                .unwrap_or_else(|| panic!("Should have found file: {}", c));
+            parsed.tokens.push((
+                range,
+                Token::Include {
+                    clearpage: t == "include",
+                    name: c.trim().to_owned(),
+                    file: name,
+                },
+            ));
+        }
+    }
+    parsed.contents = contents;
+    parsed
+}
+
+/// Reduced citations and bibliographies, and the map from each dropped duplicate to its replacement.
+type Reduced<B> = (Vec<Cite>, B, HashMap<String, String>);
+
+fn take_used_scoped(doc: &Document, th: f64) -> Reduced<Vec<Vec<BibEntry>>> {
+    let last = doc.bibliographies.len().saturating_sub(1);
+    let mut clean_cites = doc.cites.clone();
+    let mut used_bibs = vec![];
+    let mut all_replacements = HashMap::new();
+    for (scope, bib) in doc.bibliographies.iter().enumerate() {
+        let idx = (0..doc.cites.len())
+            .filter(|&i| doc.cites[i].scope.min(last) == scope)
+            .collect::<Vec<usize>>();
+        let cites = idx.iter().map(|&i| &doc.cites[i]).collect::<Vec<&Cite>>();
+        let (scope_cites, used_bib, replacements) = take_used(&bib.items, &cites, th);
+        for (i, c) in idx.into_iter().zip(scope_cites) {
+            clean_cites[i] = c;
+        }
+        used_bibs.push(used_bib);
+        all_replacements.extend(replacements);
+    }
+    (clean_cites, used_bibs, all_replacements)
+}
+
+fn take_used(raw_bib: &[BibEntry], cites: &[&Cite], th: f64) -> Reduced<Vec<BibEntry>> {
+    let (replacements, bib) = reduce_bib(raw_bib, th);
 
     let clean_cites = cites
         .iter()
         .map(|c| {
             let ll = &c.list;
             let mut l = ll
-                .into_iter()
+                .iter()
                 .map(|e| {
                     if replacements.contains_key(e) {
                         replacements.get(e).unwrap()
                     } else {
-                        &e
+                        e
                     }
                 })
                 .collect::<Vec<&String>>();
             utils::vec_dedup(&mut l);
-            let k = &c.kind;
-            let raw = &c.raw;
             Cite {
                 list: l.into_iter().map(|v| v.to_owned()).collect(),
-                kind: k.to_owned(),
-                raw: raw.to_owned(),
+                kind: c.kind.to_owned(),
+                scope: c.scope,
             }
         })
         .collect::<Vec<Cite>>();
 
     let mut set_cites = HashSet::<&String>::new();
     let mut ord_cites = Vec::<&String>::new();
-    for s in &clean_cites {
-        for c in &s.list {
-            if set_cites.insert(&c) {
-                ord_cites.push(&c);
+    for s in clean_cites.iter() {
+        for c in s.list.iter() {
+            // \nocite{*} cites every entry not cited before, in source order
+            let keys = if c == "*" {
+                raw_bib
+                    .iter()
+                    .map(|b| replacements.get(&b.key).unwrap_or(&b.key))
+                    .collect()
+            } else {
+                vec![c]
+            };
+            for k in keys {
+                if set_cites.insert(k) {
+                    ord_cites.push(k);
+                }
             }
         }
     }
 
-    let mut hash_bib = HashMap::<&String, &String>::new();
+    let mut hash_bib = HashMap::<&String, &BibEntry>::new();
 
     for b in &bib {
-        hash_bib.insert(&b.key, &b.text);
+        hash_bib.insert(&b.key, b);
     }
 
     let mut minimal_bib = Vec::<BibEntry>::new();
     for c in &ord_cites {
-        if hash_bib.contains_key(c) {
+        if let Some(b) = hash_bib.get(c) {
             minimal_bib.push(BibEntry {
                 key: c.to_string(),
-                text: hash_bib.get(c).unwrap().to_string(),
+                ..(*b).clone()
             });
         } else {
+            diagnostics::warning(format!("citation \"{}\" has no bibliography entry", c));
             minimal_bib.push(BibEntry {
                 key: c.to_string(),
+                label: None,
                 text: "ERROR, BIBENTRY NOT FOUND.".to_owned(),
+                fields: vec![],
             });
         }
     }
 
-    (clean_cites, minimal_bib)
+    (clean_cites, minimal_bib, replacements)
 }
 
-fn apply_changes(fname: &str, bib: &Vec<BibEntry>, cite: &Vec<Cite>, options: &RefactorCli) {
-    let n_fname = change_path(&fname, &options.subdir).unwrap();
-
-    let mut contents = utils::read_tex_stripped(&fname).expect("File could not be read.");
-    for v in cite {
-        contents = contents.replace(&v.raw, &format!("{}", v));
+/// Sorts a refactored bibliography, entries missing from the source always go last.
+fn sort_bib(bib: &mut [BibEntry], order: Order, position: &HashMap<String, usize>) {
+    let missing = |b: &BibEntry| !position.contains_key(&b.key);
+    match order {
+        Order::Citation => {}
+        Order::Key => bib.sort_by_cached_key(|b| (missing(b), b.key.to_lowercase())),
+        Order::Author => bib.sort_by_cached_key(|b| (missing(b), b.sort_author(), b.sort_year())),
+        Order::Year => bib.sort_by_cached_key(|b| (missing(b), b.sort_year(), b.sort_author())),
+        Order::Source => bib.sort_by_key(|b| position.get(&b.key).copied().unwrap_or(usize::MAX)),
     }
+}
 
-    let bre = find_thebibliography(&contents);
-    let n = utils::thebibliography_size(bib.len());
-
-    let bibstr = format!(
-        "\\begin{{thebibliography}}{{{}}}\n{}\n\\end{{thebibliography}}",
-        n,
-        bib.into_iter()
-            .map(|v| format!["{}", v])
-            .collect::<Vec<String>>()
-            .join("\n\n")
-    );
-    let mut ncontents = contents.to_owned();
-    for b in bre.iter() {
-        ncontents = ncontents.replace(b, &bibstr);
-    }
-    contents = ncontents;
-
+fn apply_changes(
+    doc: &Document,
+    src: &Source,
+    bibs: &[Vec<BibEntry>],
+    cites: &[Cite],
+    options: &RefactorCli,
+) {
+    let n_fname = change_path(&src.fname, &options.subdir).unwrap();
+    let contents = render(doc, src, bibs, cites, None);
+    diagnostics::info(format!("wrote {}", n_fname));
     utils::write_file(n_fname, &contents);
+}
 
-    let re = Regex::new(r"\\input\{[^}]+\}|\\include\{[^}]+\}|\\includeonly\{[^}]+\}").unwrap();
-    let re2 = Regex::new(r"\\[a-zA-Z]*\{(?P<content>.*)\}").unwrap();
-    for cap in re.captures_iter(&contents) {
-        let tok = &cap[0];
-        let match_tok = re2.captures(&tok).unwrap();
-        let c = &match_tok["content"];
-        let name = file_from_file(fname, c).expect(&("Should have found file: ".to_owned() + c));
-        apply_changes(&name, &bib, &cite, &options);
+/// How included files are inlined when flattening the document.
+struct Flatten {
+    /// Names given to `\includeonly`, `None` when every `\include` is compiled.
+    only: Option<Vec<String>>,
+    /// The only bibliography written, the others serve the same entries.
+    bibliography: Option<usize>,
+}
+
+/// Refactored contents of a source; with `flatten`, included files are rendered in place.
+fn render(
+    doc: &Document,
+    src: &Source,
+    bibs: &[Vec<BibEntry>],
+    cites: &[Cite],
+    flatten: Option<&Flatten>,
+) -> String {
+    let mut contents = String::new();
+    let mut last = 0;
+    for (range, tok) in src.tokens.iter() {
+        contents.push_str(&src.contents[last..range.start]);
+        last = range.end;
+        let dropped = |i: usize| flatten.is_some_and(|f| f.bibliography.is_some_and(|b| b != i));
+        match (tok, flatten) {
+            (Token::Cite(i), _) => contents.push_str(&format!("{}", cites[*i])),
+            (Token::Bibliography(i), _) if dropped(*i) => {}
+            (Token::Bibliography(i), _) => {
+                contents.push_str(&doc.bibliographies[*i].format_with(&bibs[*i]))
+            }
+            (Token::IncludeOnly(_), Some(_)) => {}
+            (
+                Token::Include {
+                    clearpage,
+                    name,
+                    file,
+                },
+                Some(flat),
+            ) => {
+                let id = includes::file_id(file);
+                let Some(child) = doc
+                    .sources
+                    .iter()
+                    .find(|s| includes::file_id(&s.fname) == id)
+                else {
+                    continue;
+                };
+                // LaTeX still starts a new page for files left out by \includeonly
+                let excluded = *clearpage
+                    && flat
+                        .only
+                        .as_ref()
+                        .is_some_and(|only| !only.iter().any(|o| o == name));
+                if *clearpage {
+                    contents.push_str("\\clearpage\n");
+                }
+                if !excluded {
+                    contents.push_str(&render(doc, child, bibs, cites, flatten));
+                    if *clearpage {
+                        contents.push_str("\n\\clearpage\n");
+                    }
+                }
+            }
+            (Token::Include { .. } | Token::IncludeOnly(_), None) => {
+                contents.push_str(&src.contents[range.clone()])
+            }
+        }
     }
+    contents.push_str(&src.contents[last..]);
+    contents
+}
+
+/// Writes the whole document as one file, named after the master file.
+fn write_flattened(doc: &Document, bibs: &[Vec<BibEntry>], cites: &[Cite], options: &RefactorCli) {
+    let only = doc
+        .sources
+        .iter()
+        .flat_map(|s| s.tokens.iter())
+        .find_map(|(_, t)| match t {
+            Token::IncludeOnly(names) => Some(names.to_owned()),
+            _ => None,
+        });
+    let flat = Flatten {
+        only,
+        // in scoped mode every bibliography has entries of its own
+        bibliography: if options.scoped {
+            None
+        } else {
+            doc.bibliographies.len().checked_sub(1)
+        },
+    };
+    let id = includes::file_id(&options.file);
+    let Some(master) = doc
+        .sources
+        .iter()
+        .find(|s| includes::file_id(&s.fname) == id)
+    else {
+        return;
+    };
+    let n_fname = change_path(&master.fname, &options.subdir).unwrap();
+    let contents = render(doc, master, bibs, cites, Some(&flat));
+    diagnostics::info(format!("wrote {}", n_fname));
+    utils::write_file(n_fname, &contents);
 }
 
-fn reduce_bib(bib: &Vec<BibEntry>, th: f64) -> (HashMap<String, String>, Vec<BibEntry>) {
-    let components = find_connected_components(&bib, th);
+fn reduce_bib(bib: &[BibEntry], th: f64) -> (HashMap<String, String>, Vec<BibEntry>) {
+    let components = find_connected_components(bib, th);
     let mut reps: HashMap<String, String> = HashMap::new();
     let mut red_bib: Vec<BibEntry> = Vec::new();
     for c in components.into_iter() {
@@ -213,30 +709,21 @@ fn reduce_bib(bib: &Vec<BibEntry>, th: f64) -> (HashMap<String, String>, Vec<Bib
     (reps, red_bib)
 }
 
-fn find_connected_components(bib: &Vec<BibEntry>, th: f64) -> Vec<Vec<usize>> {
+fn find_connected_components(bib: &[BibEntry], th: f64) -> Vec<Vec<usize>> {
     // Build graph of duplicates
     let mut g = HashMap::<usize, HashSet<usize>>::new();
-    for (i, bi) in bib.into_iter().enumerate() {
-        for (j, bj) in bib.into_iter().enumerate() {
+    for (i, bi) in bib.iter().enumerate() {
+        for (j, bj) in bib.iter().enumerate() {
             if j <= i {
                 continue;
             }
-            let mut ed = 2.0 * (utils::edit_distance(&bi.text, &bj.text) as f64)
-                / ((&bi.text.len() + &bj.text.len()) as f64);
+            let mut ed = thebibliography::distance(bi, bj);
             if bi.key == bj.key {
                 ed = th;
             }
             if ed <= th {
-                if g.contains_key(&i) {
-                    g.get_mut(&i).unwrap().insert(j);
-                } else {
-                    g.insert(i, HashSet::from([j]));
-                }
-                if g.contains_key(&j) {
-                    g.get_mut(&j).unwrap().insert(i);
-                } else {
-                    g.insert(j, HashSet::from([i]));
-                }
+                g.entry(i).or_default().insert(j);
+                g.entry(j).or_default().insert(i);
             }
         }
     }
@@ -250,20 +737,20 @@ fn find_connected_components(bib: &Vec<BibEntry>, th: f64) -> Vec<Vec<usize>> {
         }
         let mut q: Vec<usize> = Vec::from([k.to_owned()]);
         let mut c_comp: Vec<usize> = vec![];
-        while q.len() > 0 {
-            let e = q.pop().unwrap();
+        while let Some(e) = q.pop() {
             if n.contains(&e) {
                 continue;
             }
             c_comp.push(e);
             n.insert(e);
             let v = g.get(&e).unwrap();
-            for ne in v.into_iter() {
+            for ne in v.iter() {
                 if !n.contains(ne) {
                     q.push(*ne);
                 }
             }
         }
+        c_comp.sort();
         res.push(c_comp);
     }
     for i in 0..bib.len() {
@@ -273,21 +760,7 @@ fn find_connected_components(bib: &Vec<BibEntry>, th: f64) -> Vec<Vec<usize>> {
         res.push(vec![i]);
     }
     // return conn. comp.
-    return res;
-}
-
-fn file_from_file<'a>(path: &'a str, fname: &'a str) -> Option<String> {
-    let mut wkdir = PathBuf::from(path);
-    let _ = wkdir.pop();
-    for ext in ["", ".tex", ".latex", ".bib", ".bbl"] {
-        let mut np = wkdir.clone();
-        let name = fname.to_owned() + ext;
-        np.push(name);
-        if np.exists() {
-            return Some(np.to_str().unwrap().to_owned());
-        }
-    }
-    return None;
+    res
 }
 
 fn change_path<'a>(path: &'a str, add: &'a str) -> Option<String> {
@@ -296,54 +769,54 @@ fn change_path<'a>(path: &'a str, add: &'a str) -> Option<String> {
     let _ = wkdir.pop();
     wkdir.push(add);
     wkdir.push(name);
-    return Some(wkdir.to_str().unwrap().to_owned());
+    Some(wkdir.to_str().unwrap().to_owned())
 }
 
-fn parse_bibliography(contents: &str) -> Vec<BibEntry> {
-    let re2 = Regex::new(r"\{(.*?)\}(.*)").unwrap();
-    let bib = find_thebibliography(contents);
-    let mut res: Vec<BibEntry> = vec![];
-    for bibm in bib.iter() {
-        for s in get_bibitems(bibm).split("\\bibitem").into_iter() {
-            let st = s.trim().replace("\n", "");
-            let cp = re2.captures(&st);
-            if let Some(captured) = cp {
-                res.push(BibEntry {
-                    key: captured[1].trim().to_owned(),
-                    text: utils::clean_bib_text(captured[2].trim()),
-                });
-            }
-        }
-    }
-    return res;
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn find_thebibliography(text: &str) -> Vec<&str> {
-    let mut result = vec![];
-    let mut sub = text;
-    let start_token = "\\begin{thebibliography}";
-    let end_token = "\\end{thebibliography}";
-    loop {
-        let tok_s = sub.find(start_token);
-        if let Some(idx) = tok_s {
-            sub = &sub[idx..];
-            let tok_e = sub.find(end_token);
-            if let Some(idx_se) = tok_e {
-                let idx_e = idx_se + end_token.len();
-                result.push(&sub[0..idx_e]);
-                sub = &sub[idx_e..];
-            }
-        } else {
-            break;
-        }
+    #[test]
+    fn parses_every_citation_command() {
+        let parsed = parse_source(
+            "main.tex",
+            "\\citep[p.~3]{a} \\citeauthor*{b, c} \\parencite{d} \\nocite{*}".to_owned(),
+            None,
+        );
+        let cites = parsed
+            .cites
+            .iter()
+            .map(|c| (c.kind.as_str(), c.list.join(",")))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cites,
+            [
+                ("citep[p.~3]", "a".to_owned()),
+                ("citeauthor*", "b,c".to_owned()),
+                ("parencite", "d".to_owned()),
+                ("nocite", "*".to_owned()),
+            ]
+        );
+        assert_eq!(parsed.cites[0].to_string(), "\\citep[p.~3]{a}");
     }
-    return result;
-}
 
-fn get_bibitems(text: &str) -> &str {
-    let start_token = "\\bibitem";
-    let end_token = "\\end{thebibliography}";
-    let tok_s = text.find(start_token).unwrap();
-    let tok_e = text.find(end_token).unwrap();
-    return &text[tok_s..tok_e];
+    #[test]
+    fn nocite_star_keeps_every_entry() {
+        let bib = Thebibliography::parse(concat!(
+            "\\begin{thebibliography}{9}\n",
+            "\\bibitem{z} Z. Zed, Phys. Rev. Lett. 1, 1 (2001).\n",
+            "\\bibitem{b} B. Bee, Nature 2, 20 (2002).\n",
+            "\\bibitem{a} A. Ay, Science 3, 300 (2003).\n",
+            "\\end{thebibliography}",
+        ));
+        let cite = |kind: &str, list: &[&str]| Cite {
+            list: list.iter().map(|k| k.to_string()).collect(),
+            kind: kind.to_owned(),
+            scope: 0,
+        };
+        let cites = [cite("cite", &["b"]), cite("nocite", &["*"])];
+        let (_, used, _) = take_used(&bib.items, &cites.iter().collect::<Vec<_>>(), 0.3);
+        let keys = used.iter().map(|b| b.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["b", "z", "a"]);
+    }
 }
@@ -0,0 +1,365 @@
+//! # rekey
+//! Renames citation keys consistently across every file of a TeX document.
+//!
+//! Keys are generated from a template, colliding keys are told apart with
+//! `a`, `b`, `c`... suffixes, and the old→new mapping is written to a file,
+//! so that the rename can be undone with `--undo`.
+//!
+//! Run it as `latex-thebib rekey -f master.tex -t "{Author}{year}{Word}"`.
+use crate::diagnostics;
+use crate::includes::IncludeGraph;
+use crate::refactor;
+use crate::thebibliography::{find_command, group, BibEntry};
+use crate::unicode;
+use crate::utils;
+use clap::Args;
+use regex::{Captures, Regex};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct RekeyCli {
+    #[arg(short, long)]
+    /// Master TeX file
+    file: String,
+    #[arg(short, long, default_value = "{Author}{year}{Word}")]
+    /// Key template, made of {author}, {Author}, {year}, {word}, {Word}, {key} (the old key) and {n} (order of first citation)
+    template: String,
+    #[arg(short, long, default_value = "rekey-map.tsv")]
+    /// File, next to the master file, receiving the old→new key mapping
+    mapping: String,
+    #[arg(short, long)]
+    /// Undo a previous rename, reading the mapping from this file, next to the master file
+    undo: Option<String>,
+    #[arg(long, default_value_t = false)]
+    /// Overwrite the mapping file if it already exists
+    force: bool,
+}
+
+const STOPWORDS: [&str; 14] = [
+    "a", "an", "the", "on", "of", "in", "for", "and", "to", "with", "at", "by", "from", "is",
+];
+
+pub fn run_rekey(cli: &RekeyCli) {
+    let graph = match IncludeGraph::build(&cli.file) {
+        Ok(graph) => graph,
+        Err(e) => {
+            diagnostics::error(e);
+            return;
+        }
+    };
+    let mapping = if let Some(undo) = &cli.undo {
+        let path = beside(&cli.file, undo);
+        match read_mapping(&path) {
+            Some(m) => m.into_iter().map(|(old, new)| (new, old)).collect(),
+            None => {
+                diagnostics::error(format!("Unable to read file \"{}\"", path.display()));
+                return;
+            }
+        }
+    } else {
+        let path = beside(&cli.file, &cli.mapping);
+        if path.exists() && !cli.force {
+            diagnostics::error(format!(
+                "mapping file \"{}\" already exists, undo the previous rename or use --force",
+                path.display()
+            ));
+            return;
+        }
+        generate_keys(&refactor::parse_document(&cli.file), &cli.template)
+    };
+    let map = mapping.iter().cloned().collect::<HashMap<String, String>>();
+    if let Err(e) = rewrite_files(graph.files(), &map) {
+        diagnostics::error(e);
+        return;
+    }
+    match &cli.undo {
+        // an undone mapping is spent, the next rename may write a new one
+        Some(undo) => {
+            let _ = std::fs::remove_file(beside(&cli.file, undo));
+        }
+        // only a rename that was applied can be undone
+        None => utils::write_file(
+            beside(&cli.file, &cli.mapping).to_str().unwrap().to_owned(),
+            &format_mapping(&mapping),
+        ),
+    }
+}
+
+/// New keys in order of first citation, followed by the entries that are never cited.
+fn generate_keys(doc: &refactor::Document, template: &str) -> Vec<(String, String)> {
+    let mut entries: Vec<&BibEntry> = vec![];
+    let mut seen = HashSet::new();
+    let by_key = doc
+        .bibliographies
+        .iter()
+        .flat_map(|b| b.items.iter())
+        .map(|b| (b.key.as_str(), b))
+        .collect::<HashMap<&str, &BibEntry>>();
+    for c in doc.cites.iter().flat_map(|c| c.list.iter()) {
+        if let Some(b) = by_key.get(c.as_str()) {
+            if seen.insert(c.as_str()) {
+                entries.push(b);
+            }
+        }
+    }
+    for b in doc.bibliographies.iter().flat_map(|b| b.items.iter()) {
+        if seen.insert(b.key.as_str()) {
+            entries.push(b);
+        }
+    }
+
+    let keys = entries
+        .iter()
+        .enumerate()
+        .map(|(n, b)| expand_template(template, b, n + 1))
+        .collect::<Vec<String>>();
+    let mut count = HashMap::<&str, usize>::new();
+    for k in keys.iter() {
+        *count.entry(k).or_default() += 1;
+    }
+    // cited keys without a bibitem are left alone, new keys must not clash with them
+    let cited = doc
+        .cites
+        .iter()
+        .flat_map(|c| c.list.iter())
+        .filter(|c| !by_key.contains_key(c.as_str()))
+        .collect::<HashSet<&String>>();
+    let mut used = HashMap::<&str, usize>::new();
+    let mut mapping = vec![];
+    for (b, k) in entries.iter().zip(keys.iter()) {
+        let mut new = k.to_owned();
+        if count[k.as_str()] > 1 || cited.contains(k) {
+            let n = used.entry(k).or_default();
+            new.push_str(&utils::letter_suffix(*n));
+            *n += 1;
+        }
+        mapping.push((b.key.to_owned(), new));
+    }
+    mapping
+}
+
+fn expand_template(template: &str, b: &BibEntry, n: usize) -> String {
+    let author = ascii_word(&b.sort_author());
+    let word = b
+        .title_guess()
+        .split_whitespace()
+        .map(ascii_word)
+        .find(|w| !w.is_empty() && !STOPWORDS.contains(&w.as_str()))
+        .unwrap_or_default();
+    template
+        .replace("{author}", &author)
+        .replace("{Author}", &capitalize(&author))
+        .replace("{year}", &b.sort_year())
+        .replace("{word}", &word)
+        .replace("{Word}", &capitalize(&word))
+        .replace("{key}", &b.key)
+        .replace("{n}", &n.to_string())
+}
+
+fn ascii_word(s: &str) -> String {
+    unicode::ascii_fold(s)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn capitalize(s: &str) -> String {
+    let mut c = s.chars();
+    match c.next() {
+        Some(f) => f.to_uppercase().chain(c).collect(),
+        None => "".to_owned(),
+    }
+}
+
+fn format_mapping(mapping: &[(String, String)]) -> String {
+    mapping
+        .iter()
+        .map(|(old, new)| format!("{}\t{}\n", old, new))
+        .collect()
+}
+
+/// Path of a mapping file, relative to the directory of the master file.
+fn beside(master: &str, name: &str) -> PathBuf {
+    let mut path = PathBuf::from(master);
+    path.pop();
+    path.join(name)
+}
+
+fn read_mapping(fname: &Path) -> Option<Vec<(String, String)>> {
+    let data = std::fs::read_to_string(fname).ok()?;
+    Some(
+        data.lines()
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(old, new)| (old.trim().to_owned(), new.trim().to_owned()))
+            .collect(),
+    )
+}
+
+fn rename_list(keys: &str, map: &HashMap<String, String>) -> String {
+    keys.split(',')
+        .map(|k| match map.get(k.trim()) {
+            Some(n) => k.replace(k.trim(), n),
+            None => k.to_owned(),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Renames `\bibitem[label]{key}`, labels may hold arbitrarily nested braces.
+fn rename_bibitems(data: &str, map: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for i in find_command(data, "bibitem") {
+        let start = i + "\\bibitem".len();
+        let mut rest = &data[start..];
+        if rest.trim_start().starts_with('[') {
+            match group(rest, b'[', b']') {
+                Some((_, r)) => rest = r,
+                None => continue,
+            }
+        }
+        let Some((key, after)) = group(rest, b'{', b'}') else {
+            continue;
+        };
+        let key_end = data.len() - after.len() - 1;
+        let key_start = key_end - key.len();
+        out.push_str(&data[last..key_start]);
+        out.push_str(&rename_list(key, map));
+        last = key_end;
+    }
+    out.push_str(&data[last..]);
+    out
+}
+
+/// Rewrites all the files in memory first, then replaces them all at once.
+/// If a file cannot be replaced, the files already replaced get their
+/// original contents back, so that an error never leaves the document half
+/// renamed.
+fn rewrite_files(files: &[String], map: &HashMap<String, String>) -> Result<(), String> {
+    let re = Regex::new(refactor::CITE_COMMAND).unwrap();
+    let mut rewritten = vec![];
+    for fname in files {
+        let data = std::fs::read_to_string(fname)
+            .map_err(|e| format!("file \"{}\" cannot be read: {}", fname, e))?;
+        let new = re.replace_all(&data, |cap: &Captures| {
+            format!("{}{{{}}}", &cap["cmd"], rename_list(&cap["keys"], map))
+        });
+        let new = rename_bibitems(&new, map);
+        if new != data {
+            rewritten.push((fname, data, new));
+        }
+    }
+    let mut staged = vec![];
+    for (fname, _, data) in rewritten.iter() {
+        let tmp = format!("{}.rekey-tmp", fname);
+        if let Err(e) = std::fs::write(&tmp, data) {
+            for t in staged.iter().chain([&tmp]) {
+                let _ = std::fs::remove_file(t);
+            }
+            return Err(format!("file \"{}\" cannot be written: {}", fname, e));
+        }
+        staged.push(tmp);
+    }
+    for (i, ((fname, _, _), tmp)) in rewritten.iter().zip(staged.iter()).enumerate() {
+        if let Err(e) = std::fs::rename(tmp, fname) {
+            for t in staged[i..].iter() {
+                let _ = std::fs::remove_file(t);
+            }
+            let mut msg = format!("file \"{}\" cannot be replaced: {}", fname, e);
+            for (done, original, _) in rewritten[..i].iter() {
+                if let Err(e) = std::fs::write(done, original) {
+                    msg.push_str(&format!(
+                        ", and file \"{}\" cannot be restored: {}",
+                        done, e
+                    ));
+                }
+            }
+            return Err(msg);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refactor::{Cite, Document};
+    use crate::thebibliography::Thebibliography;
+
+    fn document(cites: &[&str], items: &str) -> Document {
+        let env = format!(
+            "\\begin{{thebibliography}}{{9}}\n{}\n\\end{{thebibliography}}",
+            items
+        );
+        Document {
+            sources: vec![],
+            cites: cites
+                .iter()
+                .map(|c| Cite {
+                    list: c.split(',').map(|k| k.to_owned()).collect(),
+                    kind: "cite".to_owned(),
+                    scope: 0,
+                })
+                .collect(),
+            bibliographies: vec![Thebibliography::parse(&env)],
+        }
+    }
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn generates_keys_in_citation_order_with_suffixes() {
+        let doc = document(
+            &["b", "a,c"],
+            "\\bibitem{a} A. Smith, Quantum things, 2020.\n\n\
+             \\bibitem{b} J. Smith, Classical things, 2020.\n\n\
+             \\bibitem{c} K. Doe, Other things, 2019.\n\n\
+             \\bibitem{d} L. Roe, Uncited things, 2018.",
+        );
+        let keys = generate_keys(&doc, "{author}{year}");
+        assert_eq!(
+            keys,
+            vec![
+                ("b".to_owned(), "smith2020a".to_owned()),
+                ("a".to_owned(), "smith2020b".to_owned()),
+                ("c".to_owned(), "doe2019".to_owned()),
+                ("d".to_owned(), "roe2018".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn new_keys_do_not_clash_with_cited_keys_without_bibitem() {
+        let doc = document(
+            &["doe2019", "c"],
+            "\\bibitem{c} K. Doe, Other things, 2019.",
+        );
+        let keys = generate_keys(&doc, "{author}{year}");
+        assert_eq!(keys, vec![("c".to_owned(), "doe2019a".to_owned())]);
+    }
+
+    #[test]
+    fn renames_key_lists() {
+        let m = map(&[("a", "x"), ("b", "y")]);
+        assert_eq!(rename_list("a, b,c", &m), "x, y,c");
+        assert_eq!(rename_list("ab", &m), "ab");
+    }
+
+    #[test]
+    fn renames_bibitems_with_nested_labels() {
+        let m = map(&[("a", "x"), ("b", "y")]);
+        let data =
+            "\\bibitem[{Smith {et al.}(2020{\\natexlab{a}})}]{a} text [b]{b}\n\\bibitem {b} more";
+        assert_eq!(
+            rename_bibitems(data, &m),
+            "\\bibitem[{Smith {et al.}(2020{\\natexlab{a}})}]{x} text [b]{b}\n\\bibitem {y} more"
+        );
+    }
+}
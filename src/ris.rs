@@ -0,0 +1,179 @@
+//! # ris
+//! RIS reader, the tagged format exported by most reference managers and
+//! publishers: one `TAG  - value` per line, records from `TY` to `ER`.
+use crate::import::Record;
+use crate::names;
+
+fn entry_type(ty: &str) -> &'static str {
+    match ty {
+        "JOUR" | "JFULL" | "MGZN" | "NEWS" | "EJOUR" => "article",
+        "BOOK" | "EBOOK" | "EDBOOK" => "book",
+        "CHAP" | "ECHAP" => "incollection",
+        "CONF" | "CPAPER" => "inproceedings",
+        "THES" => "phdthesis",
+        "RPRT" => "techreport",
+        "UNPB" => "unpublished",
+        "ELEC" | "WEB" => "online",
+        "PAT" => "patent",
+        "DATA" => "dataset",
+        _ => "misc",
+    }
+}
+
+pub fn parse(data: &str) -> Vec<Record> {
+    let mut records = vec![];
+    let mut record: Option<Record> = None;
+    let mut last_tag = String::new();
+    let mut pages = (String::new(), String::new());
+    for line in data.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim_end();
+        let b = line.as_bytes();
+        let tagged = b.len() >= 5
+            && b[..2]
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+            && &b[2..5] == b"  -";
+        if !tagged {
+            // continuation of a long value
+            if let Some(r) = record.as_mut() {
+                match last_tag.as_str() {
+                    "AB" | "N2" => r.extend("abstract", line),
+                    "N1" => r.extend("note", line),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        let tag = &line[..2];
+        let value = line[5..].trim();
+        last_tag = tag.to_owned();
+        if tag == "TY" {
+            record = Some(Record {
+                kind: entry_type(value).to_owned(),
+                ..Default::default()
+            });
+            pages = (String::new(), String::new());
+            continue;
+        }
+        let Some(r) = record.as_mut() else {
+            continue;
+        };
+        let in_container = matches!(r.kind.as_str(), "incollection" | "inproceedings");
+        match tag {
+            "ER" => {
+                let mut r = record.take().unwrap();
+                match (pages.0.as_str(), pages.1.as_str()) {
+                    ("", "") => {}
+                    (sp, "") | ("", sp) => r.add("pages", sp),
+                    (sp, ep) => r.add("pages", &format!("{}--{}", sp, ep)),
+                }
+                records.push(r);
+            }
+            "ID" => r.key = Some(value.to_owned()).filter(|k| !k.contains(char::is_whitespace)),
+            "AU" | "A1" => r.add("author", value),
+            "A2" | "ED" if in_container || r.kind == "book" => r.add("editor", value),
+            "TI" | "T1" | "CT" => r.add("title", value),
+            "T2" | "BT" if in_container => r.add("booktitle", value),
+            "T2" | "JO" | "JF" | "JA" | "J2" | "BT" => r.add("journal", value),
+            "T3" => r.add("series", value),
+            "PY" | "Y1" | "DA" => r.add_date(value),
+            "VL" => r.add("volume", value),
+            "IS" => r.add("number", value),
+            "SP" => pages.0 = value.to_owned(),
+            "EP" => pages.1 = value.to_owned(),
+            "PB" if r.kind == "phdthesis" => r.add("school", value),
+            "PB" if r.kind == "techreport" => r.add("institution", value),
+            "PB" => r.add("publisher", value),
+            "CY" => r.add("address", value),
+            "SN" if r.kind == "article" => r.add("issn", value),
+            "SN" => r.add("isbn", value),
+            "DO" => r.add("doi", value),
+            "UR" | "L2" => r.add("url", value),
+            "AB" | "N2" => r.add("abstract", value),
+            "N1" => r.add("note", value),
+            "KW" => r.add("keywords", value),
+            "ET" => r.add("edition", value),
+            "LA" => r.add("language", value),
+            _ => {}
+        }
+    }
+    records
+}
+
+/// RIS type of a BibTeX entry type, the inverse of `entry_type`.
+fn ris_type(kind: &str) -> &'static str {
+    match kind {
+        "article" => "JOUR",
+        "book" => "BOOK",
+        "incollection" | "inbook" => "CHAP",
+        "inproceedings" | "conference" => "CPAPER",
+        "phdthesis" | "mastersthesis" => "THES",
+        "techreport" => "RPRT",
+        "unpublished" => "UNPB",
+        "online" => "ELEC",
+        "patent" => "PAT",
+        "dataset" => "DATA",
+        _ => "GEN",
+    }
+}
+
+/// Writes records as RIS, field values must be plain text.
+pub fn write(records: &[Record]) -> String {
+    let mut out = String::new();
+    for r in records {
+        let mut line = |tag: &str, value: &str| out.push_str(&format!("{}  - {}\n", tag, value));
+        line("TY", ris_type(&r.kind));
+        if let Some(key) = &r.key {
+            line("ID", key);
+        }
+        for (name, value) in r.fields.iter() {
+            match name.as_str() {
+                "author" | "editor" => {
+                    let tag = if name == "author" { "AU" } else { "A2" };
+                    for n in names::split_names(value) {
+                        line(tag, n);
+                    }
+                }
+                "pages" => {
+                    let mut p = value.split(['-', '–']).filter(|p| !p.is_empty());
+                    if let Some(sp) = p.next() {
+                        line("SP", sp.trim());
+                    }
+                    if let Some(ep) = p.next() {
+                        line("EP", ep.trim());
+                    }
+                }
+                "keywords" => {
+                    for k in value.split(',') {
+                        line("KW", k.trim());
+                    }
+                }
+                "year" => line("PY", value),
+                "ids" => line("N1", &format!("Replaces: {}", value)),
+                name => {
+                    let tag = match name {
+                        "title" => "TI",
+                        "journal" | "booktitle" => "T2",
+                        "series" => "T3",
+                        "volume" => "VL",
+                        "number" => "IS",
+                        "publisher" | "school" | "institution" | "organization" => "PB",
+                        "address" => "CY",
+                        "isbn" | "issn" => "SN",
+                        "doi" => "DO",
+                        "url" => "UR",
+                        "abstract" => "AB",
+                        "note" => "N1",
+                        "edition" => "ET",
+                        "language" => "LA",
+                        _ => continue,
+                    };
+                    line(tag, value);
+                }
+            }
+        }
+        line("ER", "");
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,407 @@
+//! # thebibliography
+//! Parsing and printing of `thebibliography` environments.
+//!
+//! The widest-label argument and whatever sits between `\begin{thebibliography}`
+//! and the first `\bibitem` (e.g. the `\providecommand` setup of a .bbl file)
+//! are kept, so that a refactored environment still compiles like the original.
+//!
+//! Structured items, as written by REVTeX into .bbl files
+//! (`\BibitemOpen \bibfield{author}{\bibinfo{author}{...}} ... \BibitemShut{NoStop}`),
+//! keep their markup verbatim and additionally expose their `\bibinfo` fields.
+use crate::pages;
+use crate::unicode;
+use crate::utils;
+use regex::Regex;
+use std::fmt;
+
+#[derive(Debug, Hash, Clone)]
+pub struct BibEntry {
+    pub key: String,
+    pub label: Option<String>,
+    pub text: String,
+    pub fields: Vec<(String, String)>,
+}
+
+impl fmt::Display for BibEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(label) = &self.label {
+            write!(f, "\\bibitem[{}]{{{}}} {}", label, self.key, self.text)
+        } else {
+            write!(f, "\\bibitem{{{}}} {}", self.key, self.text)
+        }
+    }
+}
+
+impl BibEntry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sort key of the surname of the first author, from the `author` field or guessed from the leading
+    /// text of the item (`A. Smith, B. Jones, ...`, `Smith, A. and ...`).
+    pub fn sort_author(&self) -> String {
+        let text = match self.field("author") {
+            Some(a) => a.to_owned(),
+            None => plain_text(&self.text),
+        };
+        let first = text
+            .split(" and ")
+            .next()
+            .unwrap_or("")
+            .split([',', ';', ':'])
+            .next()
+            .unwrap_or("");
+        let is_initial = |w: &str| {
+            w.ends_with('.') || (w.chars().count() == 1 && w.chars().all(char::is_uppercase))
+        };
+        let words = first.split_whitespace().collect::<Vec<&str>>();
+        let surname = words
+            .iter()
+            .rev()
+            .find(|w| !is_initial(w))
+            .or(words.last())
+            .copied()
+            .unwrap_or("");
+        unicode::sort_key(surname)
+    }
+
+    /// Title, from the `title` field, an emphasised or quoted span, or the first
+    /// comma-separated segment of the text that does not look like a list of names.
+    pub fn title_guess(&self) -> String {
+        if let Some(t) = self.field("title") {
+            return t.to_owned();
+        }
+        let re = Regex::new(r"\\(?:textit|emph)\s*\{([^}]*)\}|``([^']*)''|\\(?:it|em)\s+([^},]*)")
+            .unwrap();
+        if let Some(cap) = re.captures(&self.text) {
+            let t = cap.iter().skip(1).flatten().next().unwrap().as_str();
+            return plain_text(t);
+        }
+        let text = plain_text(&self.text);
+        let segments = text.split(',').map(|s| s.trim()).collect::<Vec<&str>>();
+        title_index(&segments)
+            .map(|i| segments[i])
+            .unwrap_or("")
+            .to_owned()
+    }
+
+    /// Authors, from the `author` field or the name segments leading the text, as a
+    /// BibTeX name list: `A. Smith, B. Jones et al.` becomes `A. Smith and B. Jones and others`.
+    pub fn authors_guess(&self) -> String {
+        if let Some(a) = self.field("author") {
+            return a.to_owned();
+        }
+        let text = plain_text(&self.text);
+        let segments = text.split(',').map(|s| s.trim()).collect::<Vec<&str>>();
+        let end = title_index(&segments).unwrap_or(0);
+        let is_initials = |p: &str| {
+            p.split_whitespace()
+                .all(|w| w.ends_with('.') || (w.len() == 1 && w.chars().all(char::is_uppercase)))
+        };
+        let mut names: Vec<String> = vec![];
+        for part in segments[..end].iter().flat_map(|s| s.split(" and ")) {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            match names.last_mut() {
+                // "Smith, A." is a single name
+                Some(last) if is_initials(part) && !last.contains([' ', ',']) => {
+                    last.push_str(", ");
+                    last.push_str(part);
+                }
+                _ => match part.strip_suffix("et al.") {
+                    Some(p) => {
+                        if !p.trim().is_empty() {
+                            names.push(p.trim().to_owned());
+                        }
+                        names.push("others".to_owned());
+                    }
+                    None => names.push(part.to_owned()),
+                },
+            }
+        }
+        names.join(" and ")
+    }
+
+    /// Publication year, from the `year` field or the last year-like number of the text,
+    /// preferring one written in parentheses.
+    pub fn sort_year(&self) -> String {
+        if let Some(y) = self.field("year") {
+            return y.to_owned();
+        }
+        let text = plain_text(&self.text);
+        let re_par = Regex::new(r"\((?:[^()]*\D)?((?:1[5-9]|20)\d\d)[a-z]?\)").unwrap();
+        if let Some(cap) = re_par.captures_iter(&text).last() {
+            return cap[1].to_owned();
+        }
+        let re = Regex::new(r"\b(?:1[5-9]|20)\d\d\b").unwrap();
+        re.find_iter(&text)
+            .last()
+            .map(|m| m.as_str().to_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Index of the first comma-separated segment that does not look like a list of names.
+fn title_index(segments: &[&str]) -> Option<usize> {
+    let has_initial = |seg: &str| {
+        seg.split_whitespace()
+            .any(|w| w.ends_with('.') && w.len() <= 3)
+    };
+    let capitalized = |seg: &str| {
+        let words = seg.split_whitespace().collect::<Vec<&str>>();
+        !words.is_empty()
+            && words.len() <= 4
+            && words.iter().all(|w| {
+                w.ends_with('.') || *w == "and" || w.chars().next().is_some_and(char::is_uppercase)
+            })
+    };
+    // a name either carries initials or is a bare surname followed by them ("Smith, A.")
+    let is_name = |i: usize| {
+        let seg = segments[i];
+        seg.split(" and ").all(capitalized)
+            && (has_initial(seg)
+                || seg.contains(" and ")
+                || segments.get(i + 1).is_some_and(|n| has_initial(n)))
+    };
+    (0..segments.len()).find(|&i| !segments[i].is_empty() && !is_name(i))
+}
+
+#[derive(Debug, Clone)]
+pub struct Thebibliography {
+    pub widest: String,
+    pub preamble: String,
+    pub items: Vec<BibEntry>,
+}
+
+const BEGIN: &str = "\\begin{thebibliography}";
+const END: &str = "\\end{thebibliography}";
+
+impl Thebibliography {
+    /// Parses a whole environment, from `\begin{thebibliography}` to `\end{thebibliography}`.
+    pub fn parse(env: &str) -> Thebibliography {
+        let start = env.find(BEGIN).map(|i| i + BEGIN.len()).unwrap_or(0);
+        let end = env.rfind(END).unwrap_or(env.len());
+        let mut body = &env[start..end];
+        let mut widest = "".to_owned();
+        if let Some((arg, rest)) = group(body, b'{', b'}') {
+            widest = arg.trim().to_owned();
+            body = rest;
+        }
+        let starts = find_command(body, "bibitem");
+        let first = starts.first().copied().unwrap_or(body.len());
+        let mut items = vec![];
+        for (n, &s) in starts.iter().enumerate() {
+            let e = starts.get(n + 1).copied().unwrap_or(body.len());
+            if let Some(item) = parse_bibitem(&body[s + "\\bibitem".len()..e]) {
+                items.push(item);
+            }
+        }
+        Thebibliography {
+            widest,
+            preamble: body[..first].trim().to_owned(),
+            items,
+        }
+    }
+
+    /// Prints the environment with `items` in place of the original ones.
+    pub fn format_with(&self, items: &[BibEntry]) -> String {
+        let mut out = format!("{}{{{}}}\n", BEGIN, self.widest_for(items));
+        if !self.preamble.is_empty() {
+            out.push_str(&self.preamble);
+            out.push('\n');
+        }
+        out.push_str(
+            &items
+                .iter()
+                .map(|v| format!["{}", v])
+                .collect::<Vec<String>>()
+                .join("\n\n"),
+        );
+        out.push('\n');
+        out.push_str(END);
+        out
+    }
+
+    /// Numeric arguments are recomputed from the number of items, custom ones from the
+    /// longest `\bibitem[label]`, and kept verbatim when there is nothing to recompute them from.
+    fn widest_for(&self, items: &[BibEntry]) -> String {
+        if self.widest.is_empty() || self.widest.chars().all(|c| c.is_ascii_digit()) {
+            if !self.widest.is_empty() && self.widest.chars().any(|c| c != '9') {
+                return items.len().to_string();
+            }
+            return utils::thebibliography_size(items.len()).to_string();
+        }
+        items
+            .iter()
+            .filter_map(|b| b.label.as_ref())
+            .max_by_key(|l| l.chars().count())
+            .cloned()
+            .unwrap_or_else(|| self.widest.to_owned())
+    }
+}
+
+fn parse_bibitem(s: &str) -> Option<BibEntry> {
+    let mut rest = s;
+    let mut label = None;
+    if rest.trim_start().starts_with('[') {
+        let (l, r) = group(rest, b'[', b']')?;
+        label = Some(l.trim().to_owned());
+        rest = r;
+    }
+    let (key, text) = group(rest, b'{', b'}')?;
+    Some(BibEntry {
+        key: key.trim().to_owned(),
+        label,
+        text: utils::clean_bib_text(text.trim()),
+        fields: parse_bibinfo(text),
+    })
+}
+
+/// Collects the `\bibinfo{name}{value}` fields of a structured item, repeated names
+/// (one `\bibinfo{author}` per author) are joined with " and ".
+fn parse_bibinfo(text: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = vec![];
+    for i in find_command(text, "bibinfo") {
+        let rest = &text[i + "\\bibinfo".len()..];
+        let Some((name, rest)) = group(rest, b'{', b'}') else {
+            continue;
+        };
+        let Some((value, _)) = group(rest, b'{', b'}') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), plain_text(value));
+        if let Some(f) = fields.iter_mut().find(|(n, _)| n == name) {
+            f.1 = format!("{} and {}", f.1, value);
+        } else {
+            fields.push((name.to_owned(), value));
+        }
+    }
+    if let Some(i) = find_command(text, "doibase").first() {
+        let doi = text[i + "\\doibase".len()..]
+            .trim_start()
+            .split(|c: char| c.is_whitespace() || c == '}')
+            .next()
+            .unwrap_or("");
+        if !doi.is_empty() {
+            fields.push(("doi".to_owned(), doi.to_owned()));
+        }
+    }
+    fields
+}
+
+/// Printed text of a TeX string: accents and special characters become Unicode,
+/// other commands and grouping braces are dropped.
+pub fn plain_text(s: &str) -> String {
+    let text = unicode::to_unicode(s).replace('\u{a0}', " ");
+    utils::clean_bib_text(text.trim())
+}
+
+/// Similarity distance used for deduplication: structured items are compared field by field,
+/// everything else by the whole text. Items whose DOI, volume or pages differ are never duplicates.
+pub fn distance(a: &BibEntry, b: &BibEntry) -> f64 {
+    let field = |e: &BibEntry, name: &str| match name {
+        "pages" => e.field(name).map(pages::canonical),
+        _ => e.field(name).map(unicode::normalize),
+    };
+    if let (Some(da), Some(db)) = (field(a, "doi"), field(b, "doi")) {
+        return if da == db { 0.0 } else { 1.0 };
+    }
+    // papers of the same authors in the same volume only differ by these
+    for name in ["volume", "pages"] {
+        if let (Some(va), Some(vb)) = (field(a, name), field(b, name)) {
+            if va != vb {
+                return 1.0;
+            }
+        }
+    }
+    let mut total = 0.0;
+    let mut n = 0;
+    for name in ["author", "title", "journal", "volume", "pages", "year"] {
+        if let (Some(va), Some(vb)) = (field(a, name), field(b, name)) {
+            total += utils::text_distance(&va, &vb);
+            n += 1;
+        }
+    }
+    if n > 0 {
+        return total / n as f64;
+    }
+    utils::text_distance(&unicode::normalize(&a.text), &unicode::normalize(&b.text))
+}
+
+/// Splits a leading `open ... close` group, skipping leading whitespace and nested braces,
+/// into its content and the remaining text.
+pub fn group(s: &str, open: u8, close: u8) -> Option<(&str, &str)> {
+    let t = s.trim_start();
+    let b = t.as_bytes();
+    if b.first() != Some(&open) {
+        return None;
+    }
+    let mut level = 0;
+    for (i, &c) in b.iter().enumerate().skip(1) {
+        if level == 0 && c == close {
+            return Some((&t[1..i], &t[i + 1..]));
+        }
+        if c == b'{' {
+            level += 1;
+        }
+        if c == b'}' {
+            level -= 1;
+        }
+    }
+    None
+}
+
+/// Offsets of `\name` in `text`, ignoring longer commands sharing the same prefix.
+pub fn find_command(text: &str, name: &str) -> Vec<usize> {
+    let pat = format!("\\{}", name);
+    text.match_indices(&pat)
+        .map(|(i, _)| i)
+        .filter(|i| {
+            !text[i + pat.len()..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRL: &str = r"\bibitem [{\citenamefont {Smith}\ and\ \citenamefont {Jones}(2020)}]{KEY}%
+  \BibitemOpen
+  \bibfield  {author} {\bibinfo {author} {\bibfnamefont {A.}~\bibnamefont {Smith}}\ and\ \bibinfo {author} {\bibfnamefont {B.}~\bibnamefont {Jones}},\ }\bibfield  {journal} {\bibinfo  {journal} {Phys. Rev. Lett.}\ }\textbf {\bibinfo {volume} {124}},\ \bibinfo {pages} {PAGES} (\bibinfo {year} {2020})\BibitemShut {NoStop}%
+";
+
+    fn revtex(key: &str, pages: &str) -> String {
+        PRL.replace("KEY", key).replace("PAGES", pages)
+    }
+
+    fn items(body: &str) -> Vec<BibEntry> {
+        let env = format!(
+            "\\begin{{thebibliography}}{{9}}\n{}\\end{{thebibliography}}",
+            body
+        );
+        Thebibliography::parse(&env).items
+    }
+
+    #[test]
+    fn different_pages_are_different_papers() {
+        let items = items(&(revtex("P1", "010501") + &revtex("P2", "230402")));
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].field("pages"), Some("010501"));
+        assert_eq!(distance(&items[0], &items[1]), 1.0);
+    }
+
+    #[test]
+    fn same_paper_is_a_duplicate() {
+        let items = items(&(revtex("P1", "010501") + &revtex("P1b", "010501")));
+        assert_eq!(distance(&items[0], &items[1]), 0.0);
+    }
+}
@@ -0,0 +1,835 @@
+//! # unicode
+//! Conversion of LaTeX text to Unicode.
+//!
+//! Accent macros (`\"o`, `{\"o}`, `\'{e}`, `\v{c}`...), special characters
+//! (`\ss`, `\o`, `--`, `~`...) and simple math (`$\alpha$`) become Unicode
+//! characters, grouping braces are dropped, and text markup (`\textit`,
+//! `{\bf ...}`, `\url`...) is handed to a `Markup` implementation, so that
+//! every output format can render it in its own way. TeX output keeps math,
+//! grouping braces and unknown commands as written.
+
+/// Accent command, base letters, and the same letters with the accent applied.
+const ACCENTS: [(char, &str, &str); 15] = [
+    ('`', "aeinouwyAEINOUWYı", "àèìǹòùẁỳÀÈÌǸÒÙẀỲì"),
+    (
+        '\'',
+        "acegiklmnoprsuwyzACEGIKLMNOPRSUWYZı",
+        "áćéǵíḱĺḿńóṕŕśúẃýźÁĆÉǴÍḰĹḾŃÓṔŔŚÚẂÝŹí",
+    ),
+    (
+        '^',
+        "aceghijosuwyzACEGHIJOSUWYZı",
+        "âĉêĝĥîĵôŝûŵŷẑÂĈÊĜĤÎĴÔŜÛŴŶẐî",
+    ),
+    ('~', "aeinouvyAEINOUVYı", "ãẽĩñõũṽỹÃẼĨÑÕŨṼỸĩ"),
+    ('=', "aegiouyAEGIOUYı", "āēḡīōūȳĀĒḠĪŌŪȲī"),
+    ('u', "aegiouAEGIOUı", "ăĕğĭŏŭĂĔĞĬŎŬĭ"),
+    (
+        '.',
+        "abcdefghmnoprstwxyzABCDEFGHIMNOPRSTWXYZ",
+        "ȧḃċḋėḟġḣṁṅȯṗṙṡṫẇẋẏżȦḂĊḊĖḞĠḢİṀṄȮṖṘṠṪẆẊẎŻ",
+    ),
+    ('"', "aehiotuwxyAEHIOUWXYı", "äëḧïöẗüẅẍÿÄËḦÏÖÜẄẌŸï"),
+    ('r', "auwyAU", "åůẘẙÅŮ"),
+    ('H', "ouOU", "őűŐŰ"),
+    (
+        'v',
+        "acdeghijklnorstuzACDEGHIKLNORSTUZ",
+        "ǎčďěǧȟǐǰǩľňǒřšťǔžǍČĎĚǦȞǏǨĽŇǑŘŠŤǓŽ",
+    ),
+    (
+        'd',
+        "abdehiklmnorstuvwyzABDEHIKLMNORSTUVWYZ",
+        "ạḅḍẹḥịḳḷṃṇọṛṣṭụṿẉỵẓẠḄḌẸḤỊḲḶṂṆỌṚṢṬỤṾẈỴẒ",
+    ),
+    ('c', "cdeghklnrstCDEGHKLNRST", "çḑȩģḩķļņŗşţÇḐȨĢḨĶĻŅŖŞŢ"),
+    ('k', "aeiouAEIOU", "ąęįǫųĄĘĮǪŲ"),
+    ('b', "bdhklnrtzBDKLNRTZ", "ḇḏẖḵḻṉṟṯẕḆḎḴḺṈṞṮẔ"),
+];
+
+/// Commands that stand for a single character, in text and math mode.
+const SYMBOLS: [(&str, &str); 113] = [
+    ("ss", "ß"),
+    ("SS", "ẞ"),
+    ("o", "ø"),
+    ("O", "Ø"),
+    ("ae", "æ"),
+    ("AE", "Æ"),
+    ("oe", "œ"),
+    ("OE", "Œ"),
+    ("aa", "å"),
+    ("AA", "Å"),
+    ("l", "ł"),
+    ("L", "Ł"),
+    ("i", "ı"),
+    ("j", "ȷ"),
+    ("dh", "ð"),
+    ("DH", "Ð"),
+    ("th", "þ"),
+    ("TH", "Þ"),
+    ("ng", "ŋ"),
+    ("NG", "Ŋ"),
+    ("textendash", "–"),
+    ("textemdash", "—"),
+    ("textquoteleft", "‘"),
+    ("textquoteright", "’"),
+    ("textquotedblleft", "“"),
+    ("textquotedblright", "”"),
+    ("guillemotleft", "«"),
+    ("guillemotright", "»"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("textellipsis", "…"),
+    ("S", "§"),
+    ("P", "¶"),
+    ("copyright", "©"),
+    ("textregistered", "®"),
+    ("texttrademark", "™"),
+    ("pounds", "£"),
+    ("euro", "€"),
+    ("dag", "†"),
+    ("ddag", "‡"),
+    ("textdegree", "°"),
+    ("degree", "°"),
+    ("TeX", "TeX"),
+    ("LaTeX", "LaTeX"),
+    ("BibTeX", "BibTeX"),
+    ("textbackslash", "\\"),
+    ("textbraceleft", "{"),
+    ("textbraceright", "}"),
+    ("textasciitilde", "~"),
+    ("textasciicircum", "^"),
+    ("textunderscore", "_"),
+    ("textless", "<"),
+    ("textgreater", ">"),
+    ("textbar", "|"),
+    ("textexclamdown", "¡"),
+    ("textquestiondown", "¿"),
+    ("textperiodcentered", "·"),
+    ("textbullet", "•"),
+    ("textmu", "µ"),
+    ("textonehalf", "½"),
+    ("textpm", "±"),
+    ("pm", "±"),
+    ("times", "×"),
+    ("textperthousand", "‰"),
+    ("infty", "∞"),
+    ("leq", "≤"),
+    ("geq", "≥"),
+    ("neq", "≠"),
+    ("approx", "≈"),
+    ("sim", "∼"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("hbar", "ℏ"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("cdot", "·"),
+    ("upsilon", "υ"),
+    ("iota", "ι"),
+    ("omicron", "ο"),
+    ("Xi", "Ξ"),
+    ("Upsilon", "Υ"),
+    ("Psi", "Ψ"),
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Phi", "Φ"),
+    ("Omega", "Ω"),
+];
+
+/// Symbols that only exist in math mode.
+const MATH: [&str; 50] = [
+    "alpha",
+    "beta",
+    "gamma",
+    "delta",
+    "epsilon",
+    "varepsilon",
+    "zeta",
+    "eta",
+    "theta",
+    "kappa",
+    "lambda",
+    "mu",
+    "nu",
+    "xi",
+    "pi",
+    "rho",
+    "sigma",
+    "tau",
+    "phi",
+    "varphi",
+    "chi",
+    "psi",
+    "omega",
+    "upsilon",
+    "iota",
+    "Gamma",
+    "Delta",
+    "Theta",
+    "Lambda",
+    "Xi",
+    "Pi",
+    "Sigma",
+    "Upsilon",
+    "Phi",
+    "Psi",
+    "Omega",
+    "pm",
+    "times",
+    "infty",
+    "leq",
+    "geq",
+    "neq",
+    "approx",
+    "sim",
+    "hbar",
+    "partial",
+    "nabla",
+    "cdot",
+    "to",
+    "rightarrow",
+];
+
+/// Combining diacritical mark of each accent command, for decomposed UTF-8 input.
+const COMBINING: [(char, char); 15] = [
+    ('`', '\u{300}'),
+    ('\'', '\u{301}'),
+    ('^', '\u{302}'),
+    ('~', '\u{303}'),
+    ('=', '\u{304}'),
+    ('u', '\u{306}'),
+    ('.', '\u{307}'),
+    ('"', '\u{308}'),
+    ('r', '\u{30a}'),
+    ('H', '\u{30b}'),
+    ('v', '\u{30c}'),
+    ('d', '\u{323}'),
+    ('c', '\u{327}'),
+    ('k', '\u{328}'),
+    ('b', '\u{331}'),
+];
+
+pub enum Style {
+    Italic,
+    Bold,
+    SmallCaps,
+    Monospace,
+    Superscript,
+    Subscript,
+    Link(String),
+}
+
+/// Rendering of the text pieces and styled spans produced by `convert`.
+pub trait Markup {
+    fn text(&self, s: &str) -> String {
+        s.to_owned()
+    }
+    fn style(&self, _style: Style, content: String) -> String {
+        content
+    }
+    /// Whether the output is TeX, where math, grouping braces and unknown commands are kept
+    /// as written.
+    fn keeps_tex(&self) -> bool {
+        false
+    }
+}
+
+/// Drops all markup, keeping the text.
+pub struct Plain;
+
+impl Markup for Plain {}
+
+/// Re-emits TeX markup, so that only accents and special characters are converted: math,
+/// grouping braces, logos and unknown commands are kept as written.
+pub struct Tex;
+
+impl Markup for Tex {
+    fn text(&self, s: &str) -> String {
+        let mut out = String::new();
+        for c in s.chars() {
+            match c {
+                '\\' => out.push_str("\\textbackslash{}"),
+                '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                '\u{a0}' => out.push('~'),
+                c => out.push(c),
+            }
+        }
+        out
+    }
+    fn style(&self, style: Style, content: String) -> String {
+        let cmd = match style {
+            Style::Italic => "textit",
+            Style::Bold => "textbf",
+            Style::SmallCaps => "textsc",
+            Style::Monospace => "texttt",
+            Style::Superscript => "textsuperscript",
+            Style::Subscript => "textsubscript",
+            Style::Link(url) => return format!("\\href{{{}}}{{{}}}", url, content),
+        };
+        format!("\\{}{{{}}}", cmd, content)
+    }
+    fn keeps_tex(&self) -> bool {
+        true
+    }
+}
+
+/// Plain Unicode text of a LaTeX string.
+pub fn to_unicode(s: &str) -> String {
+    compose_marks(&convert(s, &Plain))
+}
+
+/// TeX with accent macros and special characters replaced by Unicode, markup is kept.
+pub fn tex_to_unicode(s: &str) -> String {
+    compose_marks(&convert(s, &Tex))
+}
+
+/// Comparable form of a LaTeX or Unicode string: plain text, lowercase, single spaces.
+pub fn normalize(s: &str) -> String {
+    to_unicode(s)
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Sort key of a LaTeX or Unicode string, `Öztürk` sorts as `ozturk`.
+pub fn sort_key(s: &str) -> String {
+    ascii_fold(&normalize(s))
+}
+
+/// ASCII TeX spelling of a string: accented letters become `{\"o}`, `ß` becomes `{\ss}`,
+/// dashes and quotes become their TeX ligatures; TeX markup already present is kept.
+pub fn to_latex(s: &str) -> String {
+    let mut out = String::new();
+    for c in compose_marks(s).chars() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+        let accented = ACCENTS.iter().find_map(|(a, from, to)| {
+            let i = to.chars().position(|t| t == c)?;
+            Some((*a, from.chars().nth(i)?))
+        });
+        if let Some((a, base)) = accented {
+            let base = match base {
+                'ı' => "\\i".to_owned(),
+                b => b.to_string(),
+            };
+            if a.is_ascii_alphabetic() {
+                out.push_str(&format!("{{\\{} {}}}", a, base));
+            } else {
+                out.push_str(&format!("{{\\{}{}}}", a, base));
+            }
+            continue;
+        }
+        match c {
+            '–' => out.push_str("--"),
+            '—' => out.push_str("---"),
+            '“' => out.push_str("``"),
+            '”' => out.push_str("''"),
+            '‘' => out.push('`'),
+            '’' => out.push('\''),
+            '\u{a0}' => out.push('~'),
+            c => match SYMBOLS.iter().find(|(_, sym)| sym.chars().eq([c])) {
+                Some((name, _)) if MATH.contains(name) => out.push_str(&format!("$\\{}$", name)),
+                Some((name, _)) => out.push_str(&format!("{{\\{}}}", name)),
+                None => out.push(c),
+            },
+        }
+    }
+    out
+}
+
+/// Composes letters followed by combining marks, `o\u{308}` becomes `ö`.
+fn compose_marks(s: &str) -> String {
+    let mut out: Vec<char> = vec![];
+    for c in s.chars() {
+        let composed = COMBINING.iter().find(|(_, m)| *m == c).and_then(|(a, _)| {
+            let base = out.last()?.to_string();
+            let composed = compose(*a, &base);
+            (composed != base).then_some(composed)
+        });
+        match composed {
+            Some(composed) => {
+                out.pop();
+                out.extend(composed.chars());
+            }
+            None => out.push(c),
+        }
+    }
+    out.into_iter().collect()
+}
+
+/// Closest ASCII spelling of a text: accents are dropped, `ß` becomes `ss`, `ø` becomes `o`...
+pub fn ascii_fold(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else if let Some(base) = ACCENTS.iter().find_map(|(_, from, to)| {
+            let i = to.chars().position(|t| t == c)?;
+            from.chars().nth(i)
+        }) {
+            out.push(if base == 'ı' { 'i' } else { base });
+        } else if let Some((name, _)) = SYMBOLS
+            .iter()
+            .find(|(n, sym)| n.len() <= 2 && sym.chars().eq([c]))
+        {
+            out.push_str(name);
+        }
+    }
+    out
+}
+
+pub fn convert(s: &str, markup: &dyn Markup) -> String {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut c = Converter {
+        chars,
+        pos: 0,
+        markup,
+        math: false,
+    };
+    c.run(None)
+}
+
+fn compose(accent: char, base: &str) -> String {
+    let letter = if base == "i" && "`'^~=u\"".contains(accent) {
+        "ı"
+    } else {
+        base
+    };
+    let mut chars = letter.chars();
+    if let (Some(b), None) = (chars.next(), chars.next()) {
+        if let Some((_, from, to)) = ACCENTS.iter().find(|(a, _, _)| *a == accent) {
+            if let Some(i) = from.chars().position(|f| f == b) {
+                return to.chars().nth(i).unwrap().to_string();
+            }
+        }
+    }
+    base.to_owned()
+}
+
+struct Converter<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    markup: &'a dyn Markup,
+    math: bool,
+}
+
+impl Converter<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Converts until the end of the input, or the `until` character at the current level.
+    fn run(&mut self, until: Option<char>) -> String {
+        let mut out = String::new();
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if Some(c) == until {
+                break;
+            }
+            let piece = match c {
+                '\\' => Some(self.command()),
+                '{' => {
+                    self.pos += 1;
+                    let inner = self.group_rest();
+                    if self.markup.keeps_tex() {
+                        Some(format!("{{{}}}", inner))
+                    } else {
+                        Some(inner)
+                    }
+                }
+                '$' if self.markup.keeps_tex() => Some(self.raw_math()),
+                '$' => {
+                    self.pos += 1;
+                    let math = self.math;
+                    self.math = true;
+                    let inner = self.run(Some('$'));
+                    self.math = math;
+                    self.pos += 1;
+                    Some(inner)
+                }
+                '^' | '_' if self.math => {
+                    self.pos += 1;
+                    let arg = self.argument();
+                    let style = if c == '^' {
+                        Style::Superscript
+                    } else {
+                        Style::Subscript
+                    };
+                    Some(self.markup.style(style, arg))
+                }
+                '}' => {
+                    self.pos += 1;
+                    None
+                }
+                _ => {
+                    self.pos += 1;
+                    text.push_str(&self.ligature(c));
+                    None
+                }
+            };
+            if let Some(p) = piece {
+                out.push_str(&self.markup.text(&text));
+                text.clear();
+                out.push_str(&p);
+            }
+        }
+        out.push_str(&self.markup.text(&text));
+        out
+    }
+
+    fn ligature(&mut self, c: char) -> String {
+        let next = self.peek();
+        match (c, next) {
+            ('-', Some('-')) if !self.math => {
+                self.pos += 1;
+                if self.peek() == Some('-') {
+                    self.pos += 1;
+                    return "—".to_owned();
+                }
+                "–".to_owned()
+            }
+            ('`', Some('`')) => {
+                self.pos += 1;
+                "“".to_owned()
+            }
+            ('\'', Some('\'')) => {
+                self.pos += 1;
+                "”".to_owned()
+            }
+            ('`', _) => "‘".to_owned(),
+            ('~', _) => "\u{a0}".to_owned(),
+            _ => c.to_string(),
+        }
+    }
+
+    /// Content of a group whose opening brace was consumed, old-style `{\it ...}` switches included.
+    fn group_rest(&mut self) -> String {
+        let save = self.pos;
+        if self.peek() == Some('\\') {
+            self.pos += 1;
+            let name = self.name();
+            let style = match name.as_str() {
+                "it" | "em" | "itshape" | "sl" => Some(Style::Italic),
+                "bf" | "bfseries" => Some(Style::Bold),
+                "sc" | "scshape" => Some(Style::SmallCaps),
+                "tt" | "ttfamily" => Some(Style::Monospace),
+                _ => None,
+            };
+            if let Some(style) = style {
+                self.skip_spaces();
+                let inner = self.run(Some('}'));
+                self.pos += 1;
+                return self.markup.style(style, inner);
+            }
+            self.pos = save;
+        }
+        let inner = self.run(Some('}'));
+        self.pos += 1;
+        inner
+    }
+
+    /// A `$...$` or `$$...$$` span as written, from its opening dollar.
+    fn raw_math(&mut self) -> String {
+        let delimiter = if self.peek_at(1) == Some('$') { 2 } else { 1 };
+        let start = self.pos;
+        self.pos += delimiter;
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                self.pos += 2;
+            } else if c == '$' {
+                self.pos += delimiter;
+                break;
+            } else {
+                self.pos += 1;
+            }
+        }
+        self.pos = self.pos.min(self.chars.len());
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// A balanced `open ... close` group as written, when it comes next, possibly after spaces.
+    fn raw_group(&mut self, open: char, close: char) -> Option<String> {
+        let start = self.pos;
+        self.skip_spaces();
+        if self.peek() != Some(open) {
+            self.pos = start;
+            return None;
+        }
+        let mut level = 0;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => self.pos += 1,
+                c if c == open => level += 1,
+                c if c == close => {
+                    level -= 1;
+                    if level == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.pos = self.pos.min(self.chars.len());
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_alphabetic()) {
+            name.push(c);
+            self.pos += 1;
+        }
+        name
+    }
+
+    /// Converted argument of a command: a group, a command, or a single character.
+    fn argument(&mut self) -> String {
+        self.skip_spaces();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.group_rest()
+            }
+            Some('\\') => self.command(),
+            Some(c) => {
+                self.pos += 1;
+                self.markup.text(&c.to_string())
+            }
+            None => "".to_owned(),
+        }
+    }
+
+    /// Verbatim argument, for URLs.
+    fn raw_argument(&mut self) -> String {
+        self.skip_spaces();
+        if self.peek() != Some('{') {
+            return "".to_owned();
+        }
+        self.pos += 1;
+        let mut level = 0;
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => level += 1,
+                '}' if level == 0 => break,
+                '}' => level -= 1,
+                _ => {}
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    fn command(&mut self) -> String {
+        self.pos += 1;
+        let Some(c) = self.peek() else {
+            return "".to_owned();
+        };
+        if !c.is_ascii_alphabetic() {
+            self.pos += 1;
+            if ACCENTS.iter().any(|(a, _, _)| *a == c) {
+                let arg = self.argument();
+                return compose(c, &arg);
+            }
+            return match c {
+                '\\' | ' ' | ',' | ';' | ':' | '!' | '/' | '-' if self.markup.keeps_tex() => {
+                    format!("\\{}", c)
+                }
+                '\\' | ' ' | ',' | ';' | ':' | '!' => self.markup.text(" "),
+                '/' | '-' => "".to_owned(),
+                _ => self.markup.text(&c.to_string()),
+            };
+        }
+        let name = self.name();
+        if name.len() == 1
+            && ACCENTS.iter().any(|(a, _, _)| a.to_string() == name)
+            && self.peek().is_some_and(|c| c == '{' || c.is_whitespace())
+        {
+            let arg = self.argument();
+            return compose(name.chars().next().unwrap(), &arg);
+        }
+        // TeX keeps the commands of logos and ASCII characters, there is nothing to convert
+        let symbol = SYMBOLS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .filter(|(_, sym)| !(self.markup.keeps_tex() && sym.is_ascii()));
+        if let Some((_, sym)) = symbol {
+            self.skip_spaces();
+            if self.peek() == Some('{') && self.peek_at(1) == Some('}') {
+                self.pos += 2;
+            }
+            return self.markup.text(sym);
+        }
+        let style = match name.as_str() {
+            "textit" | "emph" | "textsl" | "mathit" => Some(Style::Italic),
+            "textbf" | "mathbf" => Some(Style::Bold),
+            "textsc" => Some(Style::SmallCaps),
+            "texttt" | "mathtt" => Some(Style::Monospace),
+            "textsuperscript" => Some(Style::Superscript),
+            "textsubscript" => Some(Style::Subscript),
+            _ => None,
+        };
+        if let Some(style) = style {
+            let arg = self.argument();
+            return self.markup.style(style, arg);
+        }
+        match name.as_str() {
+            "url" => {
+                let url = self.raw_argument();
+                let text = self.markup.text(&url);
+                self.markup.style(Style::Link(url), text)
+            }
+            "href" => {
+                let url = self.raw_argument();
+                let text = self.argument();
+                self.markup.style(Style::Link(url), text)
+            }
+            "doi" => {
+                let doi = self.raw_argument();
+                let text = self.markup.text(&doi);
+                self.markup
+                    .style(Style::Link(format!("https://doi.org/{}", doi)), text)
+            }
+            _ if self.markup.keeps_tex() => {
+                // unknown commands are kept as written, with their arguments
+                let mut out = format!("\\{}", name);
+                while let Some(arg) = self
+                    .raw_group('{', '}')
+                    .or_else(|| self.raw_group('[', ']'))
+                {
+                    out.push_str(&arg);
+                }
+                out
+            }
+            _ => {
+                // unknown commands are dropped, their arguments are kept as text
+                self.skip_spaces();
+                "".to_owned()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::Html;
+
+    fn tex(s: &str) -> String {
+        tex_to_unicode(s)
+    }
+
+    fn html(s: &str) -> String {
+        compose_marks(&convert(s, &Html))
+    }
+
+    #[test]
+    fn plain_converts_accents() {
+        assert_eq!(
+            to_unicode(r#"M{\"u}ller \'{E}cole \v c \c{c}a"#),
+            "Müller École č ça"
+        );
+        assert_eq!(to_unicode(r"{\ss} \o{} \AA"), "ß ø Å");
+        assert_eq!(
+            to_unicode("pages 1--2 and ``quoted''"),
+            "pages 1–2 and “quoted”"
+        );
+    }
+
+    #[test]
+    fn plain_drops_markup_and_braces() {
+        assert_eq!(to_unicode(r"The {{Nested} Theory}"), "The Nested Theory");
+        assert_eq!(
+            to_unicode(r"\textit{Italic} and {\bf bold}"),
+            "Italic and bold"
+        );
+        assert_eq!(to_unicode(r"$\alpha$-decay"), "α-decay");
+        assert_eq!(to_unicode(r"\unknown{kept} text"), "kept text");
+    }
+
+    #[test]
+    fn tex_converts_accents_only() {
+        assert_eq!(tex(r#"M{\"u}ller \'{E}cole"#), "M{ü}ller École");
+        assert_eq!(tex(r"AT\&T 50\%"), r"AT\&T 50\%");
+        assert_eq!(tex(r"\textit{Caf\'e}"), r"\textit{Café}");
+    }
+
+    #[test]
+    fn tex_keeps_math() {
+        assert_eq!(tex(r"$\frac{1}{2}$ spin"), r"$\frac{1}{2}$ spin");
+        assert_eq!(tex(r"$x^2$ and $$a_{ij}$$"), r"$x^2$ and $$a_{ij}$$");
+        assert_eq!(tex(r"cost \$5, $\$$"), r"cost \$5, $\$$");
+    }
+
+    #[test]
+    fn tex_keeps_unknown_commands_and_braces() {
+        assert_eq!(tex(r"\LaTeX{} tools"), r"\LaTeX{} tools");
+        assert_eq!(tex(r"\LaTeX\ tools"), r"\LaTeX\ tools");
+        assert_eq!(tex(r"\cite[p.~3]{key} x"), r"\cite[p.~3]{key} x");
+        assert_eq!(
+            tex(r#"The {{Nested} {\"O}sterreich}"#),
+            r"The {{Nested} {Ö}sterreich}"
+        );
+        assert_eq!(tex(r"\mbox{\'e} x"), r"\mbox{\'e} x");
+    }
+
+    #[test]
+    fn html_renders_markup() {
+        assert_eq!(
+            html(r#"\textit{M{\"u}ller} & co"#),
+            "<i>Müller</i> &amp; co"
+        );
+        assert_eq!(html(r"$x^2$ {\bf b}"), "x<sup>2</sup> <b>b</b>");
+        assert_eq!(
+            html(r"\url{http://x.org/a_b}"),
+            "<a href=\"http://x.org/a_b\">http://x.org/a_b</a>"
+        );
+        assert_eq!(html(r"\unknown{a {b} c}"), "a b c");
+    }
+}
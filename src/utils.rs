@@ -5,19 +5,18 @@ use std::io::prelude::*;
 pub const STR_PROTECT: &str = "@#[[3G3H498FG297EGF928HF2HRG82RHFOWKDNVKJSX]]@#*";
 
 pub fn read_tex_stripped(fname: &str) -> Option<String> {
-    if let Ok(data) = std::fs::read_to_string(&fname) {
+    if let Ok(data) = std::fs::read_to_string(fname) {
         let data = data.replace("\\%", STR_PROTECT);
         let lines = data
             .lines()
-            .into_iter()
             .map(|s| s.trim())
-            .filter(|x| x.len() == 0 || x.chars().nth(0).unwrap() != '%')
+            .filter(|x| x.is_empty() || !x.starts_with('%'))
             .map(|s| s.split('%').collect::<Vec<&str>>()[0].trim())
             .collect::<Vec<&str>>();
         let data = dedup_token(&lines.join("\n"), "\n", 3);
         return Some(data.replace(STR_PROTECT, "\\%"));
     }
-    return None;
+    None
 }
 
 pub fn vec_dedup<T: Eq + Hash + Copy>(v: &mut Vec<T>) {
@@ -85,6 +84,77 @@ pub fn edit_distance(a: &str, b: &str) -> usize {
     result
 }
 
+/// Edit distance relative to the average length of the two strings.
+pub fn text_distance(a: &str, b: &str) -> f64 {
+    let length = a.chars().count() + b.chars().count();
+    if length == 0 {
+        return 0.0;
+    }
+    2.0 * (edit_distance(a, b) as f64) / (length as f64)
+}
+
+/// Disambiguation suffix: `a`, `b`, ..., `z`, `aa`, `ab`, ...
+pub fn letter_suffix(n: usize) -> String {
+    let letter = ((b'a' + (n % 26) as u8) as char).to_string();
+    if n < 26 {
+        letter
+    } else {
+        letter_suffix(n / 26 - 1) + &letter
+    }
+}
+
+/// JSON string literal, quotes included.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Identifiers and URLs are taken literally, only TeX escapes and braces are removed.
+pub fn verbatim(v: &str) -> String {
+    let v = v.trim();
+    let v = v
+        .strip_prefix("\\url{")
+        .and_then(|u| u.strip_suffix('}'))
+        .unwrap_or(v);
+    v.replace("\\_", "_")
+        .replace("\\%", "%")
+        .replace("\\&", "&")
+        .replace("\\#", "#")
+        .replace("\\~", "~")
+        .replace(['{', '}'], "")
+}
+
+/// DOI taken literally, without the resolver or `doi:` prefix it is often written with.
+pub fn bare_doi(v: &str) -> String {
+    let d = verbatim(v);
+    let d = d.trim();
+    for p in [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi:",
+    ] {
+        if d.len() > p.len() && d.is_char_boundary(p.len()) && d[..p.len()].eq_ignore_ascii_case(p)
+        {
+            return d[p.len()..].trim().to_owned();
+        }
+    }
+    d.to_owned()
+}
+
 pub fn write_file(n_fname: String, contents: &String) {
     let path = std::path::Path::new(&n_fname);
     let prefix = path.parent().unwrap();
@@ -99,15 +169,13 @@ pub fn clean_bib_text(s: &str) -> String {
         let ps = ns
             .replace("  ", " ")
             .replace("\\it ", "\\em ")
-            .replace('\n', " ")
-            .replace('\r', " ")
-            .replace('\t', " ");
+            .replace(['\n', '\r', '\t'], " ");
         if ps == ns {
             break;
         }
         ns = ps;
     }
-    return ns;
+    ns
 }
 
 pub fn dedup_token(s: &str, token: &str, reps: usize) -> String {
@@ -125,7 +193,7 @@ pub fn dedup_token(s: &str, token: &str, reps: usize) -> String {
         }
         ns = ps;
     }
-    return ns;
+    ns
 }
 
 pub fn thebibliography_size(biblen: usize) -> usize {
@@ -133,13 +201,5 @@ pub fn thebibliography_size(biblen: usize) -> usize {
     while size < biblen {
         size = size * 10 + 9;
     }
-    return size;
-}
-
-pub fn trim_braces(sa: &str) -> &str {
-    let mut s = sa.trim();
-    if s.len() > 2 && &s[0..1] == "{" && &s[s.len() - 1..] == "}" {
-        s = &s[1..s.len() - 1];
-    }
-    return s.trim();
+    size
 }
@@ -0,0 +1,129 @@
+//! # volume
+//! Patterns for the volume, number and pages segment of a `bibitem`.
+//!
+//! `%v`, `%n` and `%p` stand for the volume, number and pages fields. Text in
+//! `[...]` is written only when the fields it mentions are present, and `|`
+//! separates alternatives: the first one whose fields outside brackets are
+//! all present is used. `%%`, `%[`, `%]` and `%|` write the character itself.
+//!
+//! The default `\textbf{%v}[(%n)][:%p]|(%n)[:%p]|%p` gives `\textbf{12}(3):45`,
+//! `\textbf{12}:45`, `(3):45` or `45`.
+pub const DEF_VOLUME_FORMAT: &str = r"\textbf{%v}[(%n)][:%p]|(%n)[:%p]|%p";
+
+#[derive(Clone)]
+enum Token {
+    Text(String),
+    /// `v`, `n` or `p`
+    Field(char),
+    Optional(Vec<Token>),
+}
+
+#[derive(Clone)]
+pub struct Pattern {
+    alternatives: Vec<Vec<Token>>,
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern, String> {
+        let mut chars = pattern.chars();
+        let (alternatives, end) = parse_sequence(&mut chars)?;
+        if end == Some(']') {
+            return Err("unmatched \"]\"".to_owned());
+        }
+        Ok(Pattern { alternatives })
+    }
+
+    /// Formats the fields, `field` gives the value of `v`, `n` or `p`; empty when no
+    /// alternative applies.
+    pub fn format(&self, field: &dyn Fn(char) -> Option<String>) -> String {
+        match self.alternatives.iter().find(|alt| applies(alt, field)) {
+            Some(alt) => render(alt, field),
+            None => String::new(),
+        }
+    }
+}
+
+/// Tokens up to the end of the pattern or up to `]`, split at top-level `|`.
+fn parse_sequence(chars: &mut std::str::Chars) -> Result<(Vec<Vec<Token>>, Option<char>), String> {
+    let mut alternatives = vec![];
+    let mut tokens = vec![];
+    let mut text = String::new();
+    let flush = |text: &mut String, tokens: &mut Vec<Token>| {
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(text)));
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => match chars.next() {
+                Some(f @ ('v' | 'n' | 'p')) => {
+                    flush(&mut text, &mut tokens);
+                    tokens.push(Token::Field(f));
+                }
+                Some(e @ ('%' | '[' | ']' | '|')) => text.push(e),
+                Some(o) => return Err(format!("unknown placeholder \"%{}\"", o)),
+                None => return Err("\"%\" at the end of the pattern".to_owned()),
+            },
+            '[' => {
+                flush(&mut text, &mut tokens);
+                let (inner, end) = parse_sequence(chars)?;
+                if end != Some(']') {
+                    return Err("unmatched \"[\"".to_owned());
+                }
+                if inner.len() > 1 {
+                    return Err("\"|\" inside \"[...]\"".to_owned());
+                }
+                tokens.push(Token::Optional(
+                    inner.into_iter().next().unwrap_or_default(),
+                ));
+            }
+            ']' => {
+                flush(&mut text, &mut tokens);
+                alternatives.push(tokens);
+                return Ok((alternatives, Some(']')));
+            }
+            '|' => {
+                flush(&mut text, &mut tokens);
+                alternatives.push(std::mem::take(&mut tokens));
+            }
+            c => text.push(c),
+        }
+    }
+    flush(&mut text, &mut tokens);
+    alternatives.push(tokens);
+    Ok((alternatives, None))
+}
+
+/// A sequence is written when its fields outside brackets are all present, and it writes at
+/// least one field.
+fn applies(tokens: &[Token], field: &dyn Fn(char) -> Option<String>) -> bool {
+    let mandatory = fields(tokens, false);
+    mandatory.iter().all(|f| field(*f).is_some())
+        && (!mandatory.is_empty() || fields(tokens, true).iter().any(|f| field(*f).is_some()))
+}
+
+/// Fields of a sequence, those in brackets too when `optional`.
+fn fields(tokens: &[Token], optional: bool) -> Vec<char> {
+    let mut out = vec![];
+    for t in tokens {
+        match t {
+            Token::Field(f) => out.push(*f),
+            Token::Optional(inner) if optional => out.extend(fields(inner, true)),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn render(tokens: &[Token], field: &dyn Fn(char) -> Option<String>) -> String {
+    let mut out = String::new();
+    for t in tokens {
+        match t {
+            Token::Text(s) => out.push_str(s),
+            Token::Field(f) => out.push_str(&field(*f).unwrap_or_default()),
+            Token::Optional(inner) if applies(inner, field) => out.push_str(&render(inner, field)),
+            Token::Optional(_) => {}
+        }
+    }
+    out
+}
@@ -0,0 +1,65 @@
+//! # watch
+//! Polling watch mode: runs a command, then runs it again whenever one of
+//! its input files changes, until interrupted with Ctrl-C.
+//!
+//! Files are compared by modification time and size. A burst of changes,
+//! like an editor writing a backup and then the file, triggers a single run
+//! once the files have been quiet for a short while.
+use crate::diagnostics;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+const POLL: Duration = Duration::from_millis(200);
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Modification time and size of a file, `None` when it cannot be read.
+pub type Stamp = Option<(SystemTime, u64)>;
+
+pub fn stamp(fname: &str) -> Stamp {
+    let meta = std::fs::metadata(fname).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+fn stamps(files: &[String]) -> Vec<Stamp> {
+    files.iter().map(|f| stamp(f)).collect()
+}
+
+/// Runs `run`, then again after every change to the files listed by `files`, which is
+/// asked again after each run since the inputs may change.
+pub fn watch(mut files: impl FnMut() -> Vec<String>, mut run: impl FnMut()) -> ! {
+    loop {
+        diagnostics::reset();
+        run();
+        diagnostics::finish();
+        let watched = files();
+        diagnostics::info(format!(
+            "watching {} files, press Ctrl-C to stop",
+            watched.len()
+        ));
+        let before = stamps(&watched);
+        let mut last = before.clone();
+        loop {
+            sleep(POLL);
+            let now = stamps(&watched);
+            if now != last {
+                last = now;
+                break;
+            }
+        }
+        loop {
+            sleep(DEBOUNCE);
+            let now = stamps(&watched);
+            if now == last {
+                break;
+            }
+            last = now;
+        }
+        let changed = watched
+            .iter()
+            .zip(before.iter().zip(last.iter()))
+            .filter(|(_, (b, l))| b != l)
+            .map(|(f, _)| f.as_str())
+            .collect::<Vec<&str>>();
+        diagnostics::info(format!("{} changed, running again", changed.join(", ")));
+    }
+}
@@ -0,0 +1,123 @@
+//! # web
+//! HTML and Markdown bibliographies, for publication pages.
+//!
+//! Items are formatted as TeX by `compile`, then converted: accents become
+//! Unicode, `\textsc`/`\textit`/`\textbf` become the matching markup and DOIs
+//! and URLs become links. Every item gets an anchor named after its cite
+//! label, so that other pages can link to a specific paper.
+use crate::unicode::{self, Markup, Style};
+
+pub struct WebItem {
+    /// Anchor of the item, the cite label.
+    pub id: String,
+    /// Item text as TeX.
+    pub tex: String,
+    pub doi: Option<String>,
+    pub url: Option<String>,
+}
+
+pub struct Html;
+
+impl Markup for Html {
+    fn text(&self, s: &str) -> String {
+        escape_html(s)
+    }
+    fn style(&self, style: Style, content: String) -> String {
+        match style {
+            Style::Italic => format!("<i>{}</i>", content),
+            Style::Bold => format!("<b>{}</b>", content),
+            Style::SmallCaps => format!(
+                "<span style=\"font-variant: small-caps\">{}</span>",
+                content
+            ),
+            Style::Monospace => format!("<code>{}</code>", content),
+            Style::Superscript => format!("<sup>{}</sup>", content),
+            Style::Subscript => format!("<sub>{}</sub>", content),
+            Style::Link(url) => format!("<a href=\"{}\">{}</a>", escape_html(&url), content),
+        }
+    }
+}
+
+pub struct Markdown;
+
+impl Markup for Markdown {
+    fn text(&self, s: &str) -> String {
+        let mut out = String::new();
+        for c in s.chars() {
+            if "\\`*_[]<>#|".contains(c) {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out
+    }
+    fn style(&self, style: Style, content: String) -> String {
+        if content.trim().is_empty() {
+            return content;
+        }
+        match style {
+            Style::Italic => format!("*{}*", content),
+            Style::Bold => format!("**{}**", content),
+            // Markdown has no small caps
+            Style::SmallCaps => content,
+            Style::Monospace => format!("`{}`", content),
+            Style::Superscript => format!("<sup>{}</sup>", content),
+            Style::Subscript => format!("<sub>{}</sub>", content),
+            Style::Link(url) => format!("[{}](<{}>)", content, url),
+        }
+    }
+}
+
+/// Ordered list, one `<li>` per item with the cite label as `id`.
+pub fn write_html(items: &[WebItem]) -> String {
+    let mut out = "<ol class=\"bibliography\">\n".to_owned();
+    for item in items {
+        out.push_str(&format!(
+            "  <li id=\"{}\">{}</li>\n",
+            escape_html(&item.id),
+            render(item, &Html)
+        ));
+    }
+    out.push_str("</ol>");
+    out
+}
+
+/// Numbered list, each item starting with an empty HTML anchor.
+pub fn write_markdown(items: &[WebItem]) -> String {
+    items
+        .iter()
+        .enumerate()
+        .map(|(n, item)| {
+            format!(
+                "{}. <a id=\"{}\"></a>{}",
+                n + 1,
+                escape_html(&item.id),
+                render(item, &Markdown)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn render(item: &WebItem, markup: &dyn Markup) -> String {
+    let mut out = unicode::convert(&item.tex, markup).trim().to_owned();
+    if let Some(doi) = &item.doi {
+        let link = format!("https://doi.org/{}", doi);
+        let text = markup.text(&format!("doi:{}", doi));
+        out.push(' ');
+        out.push_str(&markup.style(Style::Link(link), text));
+    }
+    if let Some(url) = &item.url {
+        let text = markup.text(url);
+        out.push(' ');
+        out.push_str(&markup.style(Style::Link(url.to_owned()), text));
+    }
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}